@@ -1,3 +1,10 @@
+use crate::db::record::Network;
+use crate::orbit::OrbitSource;
+use crate::refraction::{RefractionModel, Weather};
+use crate::source::{InputFormat, SourceKind};
+use crate::ubx::CnoSource;
+
+#[derive(Debug, Clone)]
 pub struct Config {
     pub min_elevation: f64,
     pub max_elevation: f64,
@@ -6,6 +13,66 @@ pub struct Config {
     pub min_height: f64,
     pub max_height: f64,
     pub step_size: f64,
+    pub keep_partial_gsv_groups: bool,
+    /// Constellations to analyse; observations of any other network are dropped.
+    pub networks: Vec<Network>,
+    /// GGA fixes with a larger HDOP are left out of the station position.
+    pub max_position_hdop: f64,
+    /// Epochs with a larger PDOP are dropped before arc detection.
+    pub max_pdop: Option<f64>,
+    /// Epochs with a larger pseudorange residual RMS (m) are dropped before arc detection.
+    pub max_range_rms: Option<f64>,
+    /// Drop epochs in which the receiver had no 3D fix.
+    pub require_3d_fix: bool,
+    /// Drop satellites that were not used in the position fix. Off by default,
+    /// as receivers tend to exclude exactly the low satellites GNSS-IR needs.
+    pub require_satellite_in_use: bool,
+    /// Drop satellites flagged by the receiver's fault detection.
+    pub drop_faulty_satellites: bool,
+    /// UBX message the C/N0 of u-blox input is taken from.
+    pub ubx_cno_source: CnoSource,
+    /// Largest time gap (s) within an arc; a longer gap starts a new one.
+    pub max_arc_gap: f64,
+    /// Arcs shorter than this (s) are rejected.
+    pub min_arc_duration: f64,
+    /// Arcs sweeping fewer degrees of elevation are rejected.
+    pub min_elevation_span: f64,
+    /// Arcs with fewer records are rejected.
+    pub min_arc_points: usize,
+    /// Largest change (degrees per second) in elevation rate from one pair of
+    /// records to the next; a larger jump marks broken geometry and rejects
+    /// the arc. Only useful with geometry from orbits, as whole-degree
+    /// receiver elevations jump all the time.
+    pub max_elevation_rate_jump: Option<f64>,
+    /// Width (degrees) of the azimuth sectors arcs are split into, counted
+    /// from north; 90 gives quadrants. None keeps arcs whole in azimuth.
+    pub azimuth_sector_width: Option<f64>,
+    /// Degree of the polynomial in sin(elevation) removing the direct signal
+    /// from the linearised SNR before spectral analysis.
+    pub snr_detrend_degree: usize,
+    /// Where satellite elevation and azimuth come from.
+    pub orbits: OrbitSource,
+    /// Correction of elevation angles for tropospheric bending before spectral analysis.
+    pub refraction: RefractionModel,
+    /// Measured surface pressure and temperature at the station, e.g. from a
+    /// nearby weather station. They take the place of whatever the input
+    /// reports and of the standard atmosphere.
+    pub weather: Option<Weather>,
+    /// Where observations are read from.
+    pub source: SourceKind,
+    /// Data format of the source; None to tell it from the file name.
+    pub input_format: Option<InputFormat>,
+}
+
+impl Config {
+    /// Whether an observation at this elevation and azimuth (degrees) lies
+    /// within the configured window.
+    pub fn accepts_geometry(&self, elevation: f64, azimuth: f64) -> bool {
+        elevation >= self.min_elevation
+            && elevation <= self.max_elevation
+            && azimuth >= self.min_azimuth
+            && azimuth <= self.max_azimuth
+    }
 }
 
 impl Default for Config {
@@ -18,6 +85,35 @@ impl Default for Config {
             min_height: 5.0,
             max_height: 30.0,
             step_size: 0.05,
+            keep_partial_gsv_groups: true,
+            // SBAS satellites are geostationary, so their arcs never sweep elevation.
+            networks: vec![
+                Network::GPS,
+                Network::Galileo,
+                Network::BeiDou,
+                Network::GLONASS,
+                Network::QZSS,
+                Network::NavIC,
+            ],
+            max_position_hdop: 5.0,
+            max_pdop: None,
+            max_range_rms: None,
+            require_3d_fix: false,
+            require_satellite_in_use: false,
+            drop_faulty_satellites: true,
+            ubx_cno_source: CnoSource::RawX,
+            max_arc_gap: 120.0,
+            min_arc_duration: 120.0,
+            min_elevation_span: 2.0,
+            min_arc_points: 10,
+            max_elevation_rate_jump: None,
+            azimuth_sector_width: None,
+            snr_detrend_degree: 4,
+            orbits: OrbitSource::Receiver,
+            refraction: RefractionModel::None,
+            weather: None,
+            source: SourceKind::File("data/nmea2.txt".to_string()),
+            input_format: None,
         }
     }
 }
\ No newline at end of file
@@ -1,22 +1,99 @@
+use std::fmt;
+
+use crate::db::record::{Band, Record};
+use crate::time::Epoch;
+
+/// Whether the satellite climbs or sinks over an arc.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArcDirection {
+    Rising,
+    Setting,
+}
+
+impl fmt::Display for ArcDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArcDirection::Rising => write!(f, "rising"),
+            ArcDirection::Setting => write!(f, "setting"),
+        }
+    }
+}
+
+/// Azimuth range (degrees, start inclusive, end exclusive) an arc was limited to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AzimuthSector {
+    pub start: f64,
+    pub end: f64,
+}
+
+impl fmt::Display for AzimuthSector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}°", self.start, self.end)
+    }
+}
+
+/// One observation of an arc, copied out of the record it came from so the
+/// arc stays valid when old records are evicted from the database.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ArcSample {
+    pub time: Epoch,
+    pub elevation: f64,
+    pub azimuth: f64,
+    pub snr: f64,
+}
+
+impl From<&Record> for ArcSample {
+    fn from(record: &Record) -> Self {
+        ArcSample {
+            time: record.time,
+            elevation: record.elevation,
+            azimuth: record.azimuth,
+            snr: record.snr,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Arc {
     pub sat_id: u32,
-    pub time_start: i64,
-    pub time_end: i64,
-    pub record_indices: Vec<usize>,
+    pub band: Band,
+    pub time_start: Epoch,
+    pub time_end: Epoch,
+    /// Samples in time order.
+    pub samples: Vec<ArcSample>,
+    pub direction: ArcDirection,
+    /// Set when arcs are split by azimuth sector.
+    pub azimuth_sector: Option<AzimuthSector>,
+    /// SNR with the direct signal removed, as a linear amplitude, one value
+    /// per sample. Empty until `gnssir::correct_arc_snr` has run; the raw
+    /// dB-Hz values stay in `samples`.
+    pub detrended_snr: Vec<f64>,
 }
 
 impl Arc {
-    pub fn new(sat_id: u32, time_start: i64, time_end: i64, record_indices: Vec<usize>) -> Self {
+    pub fn new(
+        sat_id: u32,
+        band: Band,
+        time_start: Epoch,
+        time_end: Epoch,
+        samples: Vec<ArcSample>,
+        direction: ArcDirection,
+        azimuth_sector: Option<AzimuthSector>,
+    ) -> Self {
         Arc {
             sat_id,
+            band,
             time_start,
             time_end,
-            record_indices,
+            samples,
+            direction,
+            azimuth_sector,
+            detrended_snr: Vec::new(),
         }
     }
 }
 
+#[derive(Default)]
 pub struct ArcDatabase {
     pub arcs: Vec<Arc>,
 }
@@ -36,7 +113,13 @@ impl ArcDatabase {
         self.arcs.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.arcs.is_empty()
+    }
+
     pub fn check_memory(&self) -> usize {
-        std::mem::size_of_val(&self.arcs) + self.arcs.capacity() * std::mem::size_of::<Arc>()
+        std::mem::size_of_val(&self.arcs)
+            + self.arcs.capacity() * std::mem::size_of::<Arc>()
+            + self.arcs.iter().map(|arc| arc.samples.capacity() * std::mem::size_of::<ArcSample>() + arc.detrended_snr.capacity() * std::mem::size_of::<f64>()).sum::<usize>()
     }
 }
\ No newline at end of file
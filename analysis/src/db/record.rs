@@ -1,22 +1,105 @@
 use std::collections::VecDeque;
 
+use crate::time::Epoch;
 
-#[derive(Debug, Clone, Copy)]
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Network {
     GPS,
     Galileo,
     BeiDou,
     GLONASS,
+    QZSS,
+    NavIC,
+    SBAS,
     Unknown,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Carrier frequencies per constellation. Signals sharing a carrier (e.g. GPS
+/// L2C and L2P) map to the same band, as they see the same reflection geometry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Band {
+    // GPS, QZSS, SBAS and NavIC
     L1,
+    L2,
     L5,
+    /// QZSS L6
+    L6,
+    /// NavIC S-band
+    S,
+    // GLONASS (FDMA, nominal centre frequencies)
+    G1,
+    G2,
+    // Galileo
+    E1,
+    E5a,
+    E5b,
+    E5,
+    E6,
+    // BeiDou
+    B1I,
+    B1C,
+    B2a,
+    /// B2I and B2b, both on 1207.14 MHz
+    B2b,
+    B2,
+    B3,
     Unknown,
 }
 
+impl Band {
+    /// Carrier frequency in Hz.
+    pub fn frequency(&self) -> Option<f64> {
+        let mhz = match self {
+            Band::L1 | Band::E1 | Band::B1C => 1575.42,
+            Band::L2 => 1227.60,
+            Band::L5 | Band::E5a | Band::B2a => 1176.45,
+            Band::G1 => 1602.0,
+            Band::G2 => 1246.0,
+            Band::E5b | Band::B2b => 1207.14,
+            Band::E5 | Band::B2 => 1191.795,
+            Band::E6 | Band::L6 => 1278.75,
+            Band::S => 2492.028,
+            Band::B1I => 1561.098,
+            Band::B3 => 1268.52,
+            Band::Unknown => return None,
+        };
+        Some(mhz * 1e6)
+    }
+
+    /// Carrier wavelength in meters.
+    pub fn wavelength(&self) -> Option<f64> {
+        self.frequency().map(|f| 299_792_458.0 / f)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FixMode {
+    #[default]
+    Unknown,
+    NoFix,
+    Fix2D,
+    Fix3D,
+}
+
+/// Receiver-reported quality of the epoch a record belongs to. Fields are
+/// `None` when the input carries no such information.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Quality {
+    pub fix_mode: FixMode,
+    pub pdop: Option<f64>,
+    pub hdop: Option<f64>,
+    pub vdop: Option<f64>,
+    /// RMS of the pseudorange residuals (m).
+    pub range_rms: Option<f64>,
+    /// 3D standard deviation of the position (m).
+    pub position_std: Option<f64>,
+    /// Whether the satellite was used in the position fix.
+    pub in_use: Option<bool>,
+    /// Whether the receiver's fault detection flagged the satellite as failed.
+    pub faulty: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct Record {
     pub id: u32,
@@ -24,11 +107,53 @@ pub struct Record {
     pub elevation: f64,
     pub azimuth: f64,
     pub snr: f64,
-    pub time: i64,
+    pub time: Epoch,
     pub network: Network,
     pub band: Band,
+    pub quality: Quality,
 }
 
+impl Record {
+    /// Unique id per network, band and satellite, used to group records into arcs.
+    pub fn make_id(network: Network, band: Band, satellite: u32) -> u32 {
+        (network as u32 + 1) * 100_000 + (band as u32) * 1000 + satellite
+    }
+}
+
+/// An L1 record of `satellite` at `time` with default quality, for tests to
+/// adjust with struct update syntax.
+#[cfg(test)]
+pub(crate) fn test_record(network: Network, satellite: u32, time: Epoch) -> Record {
+    Record {
+        id: Record::make_id(network, Band::L1, satellite),
+        satellite,
+        elevation: 30.0,
+        azimuth: 90.0,
+        snr: 40.0,
+        time,
+        network,
+        band: Band::L1,
+        quality: Quality::default(),
+    }
+}
+
+/// Several signals can share a band (e.g. GPS L1 C/A and L1C), and thus a
+/// record id. Keeps the first record of each id and epoch in `records[from..]`,
+/// so an arc never gets two samples at one epoch. Returns how many were dropped.
+pub fn keep_first_signal(records: &mut Vec<Record>, from: usize) -> usize {
+    let tail = records.split_off(from);
+    let before = tail.len();
+    let mut seen: Vec<(u32, Epoch)> = Vec::new();
+    for rec in tail {
+        if !seen.contains(&(rec.id, rec.time)) {
+            seen.push((rec.id, rec.time));
+            records.push(rec);
+        }
+    }
+    before - (records.len() - from)
+}
+
+#[derive(Default)]
 pub struct RecordDatabase {
     pub records: VecDeque<Record>,
 }
@@ -44,10 +169,22 @@ impl RecordDatabase {
         self.records.extend(records);
     }
 
+    /// Drops records from the front until the oldest is at `time` or later,
+    /// returning how many were dropped. Records must be in time order.
+    pub fn evict_before(&mut self, time: Epoch) -> usize {
+        let n = self.records.partition_point(|rec| rec.time < time);
+        self.records.drain(..n);
+        n
+    }
+
     pub fn len(&self) -> usize {
         self.records.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
     pub fn check_memory(&self) -> usize {
         std::mem::size_of_val(&self.records) + self.records.capacity() * std::mem::size_of::<Record>()
     }
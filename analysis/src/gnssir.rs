@@ -1,126 +1,571 @@
 use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::ops::Range;
 
-use crate::db::record::Record;
-use crate::db::arc::{Arc};
+use crate::db::record::{Band, FixMode, Record};
+use crate::db::arc::{Arc, ArcDirection, ArcSample, AzimuthSector};
 use crate::config::Config;
 use crate::math::lombscargle;
+use crate::orbit::OrbitSource;
+use crate::time::Epoch;
+use crate::refraction::{RefractionModel, Weather};
 
-pub fn find_arcs(records: &VecDeque<Record>) -> Vec<Arc> {
-    let n_records = records.len();
-    if n_records == 0 {
-        return Vec::new();
+/// Whether a record's epoch and satellite pass the quality options in `config`.
+/// Checks for which the input carried no information always pass.
+pub fn passes_quality_checks(record: &Record, config: &Config) -> bool {
+    let q = &record.quality;
+
+    if let (Some(max), Some(pdop)) = (config.max_pdop, q.pdop) && pdop > max {
+        return false;
+    }
+    if let (Some(max), Some(rms)) = (config.max_range_rms, q.range_rms) && rms > max {
+        return false;
+    }
+    if config.require_3d_fix && matches!(q.fix_mode, FixMode::NoFix | FixMode::Fix2D) {
+        return false;
+    }
+    if config.require_satellite_in_use && q.in_use == Some(false) {
+        return false;
+    }
+    if config.drop_faulty_satellites && q.faulty {
+        return false;
+    }
+
+    true
+}
+
+/// How far (degrees) the elevation must come back from its highest or lowest
+/// value before a turn is taken as real. Receivers report whole degrees, so
+/// a single-degree flicker must not split an arc.
+const DIRECTION_HYSTERESIS: f64 = 1.0;
+
+/// Finds the turning points of a time-ordered run of elevations as they come
+/// in, splitting it into rising and setting segments. A turning point is put
+/// in the middle of any plateau of equal elevations at the extreme, and
+/// closes the segment before it.
+#[derive(Debug, Clone, Default)]
+struct DirectionSplitter {
+    elevations: Vec<f64>,
+    direction: Option<ArcDirection>,
+    /// Position where the current segment starts.
+    start: usize,
+    // Positions of the first and last record at the lowest and at the
+    // highest elevation in the current segment.
+    low: usize,
+    low_last: usize,
+    high: usize,
+    high_last: usize,
+}
+
+impl DirectionSplitter {
+    /// Adds the next elevation, returning the segment it closed, if any, as
+    /// a range of positions.
+    fn push(&mut self, e: f64) -> Option<(ArcDirection, Range<usize>)> {
+        let k = self.elevations.len();
+        self.elevations.push(e);
+        if k == self.start {
+            (self.low, self.low_last, self.high, self.high_last) = (k, k, k, k);
+            return None;
+        }
+        let elev = &self.elevations;
+        if e < elev[self.low] {
+            self.low = k;
+        }
+        if e <= elev[self.low] {
+            self.low_last = k;
+        }
+        if e > elev[self.high] {
+            self.high = k;
+        }
+        if e >= elev[self.high] {
+            self.high_last = k;
+        }
+        let turn = match self.direction {
+            None => {
+                if e - elev[self.low] > DIRECTION_HYSTERESIS {
+                    self.direction = Some(ArcDirection::Rising);
+                } else if elev[self.high] - e > DIRECTION_HYSTERESIS {
+                    self.direction = Some(ArcDirection::Setting);
+                }
+                None
+            }
+            Some(ArcDirection::Rising) if elev[self.high] - e > DIRECTION_HYSTERESIS => Some((self.high + self.high_last) / 2),
+            Some(ArcDirection::Setting) if e - elev[self.low] > DIRECTION_HYSTERESIS => Some((self.low + self.low_last) / 2),
+            Some(_) => None,
+        };
+        let (end, d) = (turn?, self.direction?);
+        let segment = self.start..end + 1;
+        self.start = end + 1;
+        self.direction = Some(match d {
+            ArcDirection::Rising => ArcDirection::Setting,
+            ArcDirection::Setting => ArcDirection::Rising,
+        });
+        // Restart the extrema within the new segment.
+        let by_elevation = |x: &usize, y: &usize| elev[*x].total_cmp(&elev[*y]);
+        self.low = (self.start..=k).min_by(by_elevation).unwrap_or(k);
+        self.low_last = (self.start..=k).rev().min_by(by_elevation).unwrap_or(k);
+        self.high = (self.start..=k).rev().max_by(by_elevation).unwrap_or(k);
+        self.high_last = (self.start..=k).max_by(by_elevation).unwrap_or(k);
+        Some((d, segment))
+    }
+
+    /// Forgets the elevations before the open segment, returning how many
+    /// were dropped; positions count from the first one kept afterwards.
+    fn drain_closed(&mut self) -> usize {
+        let n = self.start;
+        self.elevations.drain(..n);
+        self.start = 0;
+        self.low -= n;
+        self.low_last -= n;
+        self.high -= n;
+        self.high_last -= n;
+        n
+    }
+
+    /// The segment still open, if any.
+    fn finish(&self) -> Option<(ArcDirection, Range<usize>)> {
+        let end = self.elevations.len();
+        if self.start >= end {
+            return None;
+        }
+        // Too little movement to tell: go by the overall change.
+        let d = self.direction.unwrap_or(if self.elevations[end - 1] >= self.elevations[self.start] {
+            ArcDirection::Rising
+        } else {
+            ArcDirection::Setting
+        });
+        Some((d, self.start..end))
     }
+}
 
-    // Group records by ID
-    let mut by_id: HashMap<u32, Vec<usize>> = HashMap::new();
-    for (i, rec) in records.iter().enumerate() {
-        by_id.entry(rec.id).or_default().push(i);
+/// Splits a time-ordered run of samples at elevation extrema into rising and
+/// setting segments.
+fn split_by_direction(samples: Vec<ArcSample>) -> Vec<(ArcDirection, Vec<ArcSample>)> {
+    let mut splitter = DirectionSplitter::default();
+    let mut segments: Vec<(ArcDirection, Vec<ArcSample>)> = samples
+        .iter()
+        .filter_map(|sample| splitter.push(sample.elevation))
+        .map(|(d, range)| (d, samples[range].to_vec()))
+        .collect();
+    if let Some((d, range)) = splitter.finish() {
+        segments.push((d, samples[range].to_vec()));
     }
+    segments
+}
 
-    let mut arcs = Vec::new();
+/// How far (degrees) the azimuth may stray past a sector boundary before
+/// the arc is taken to have left the sector, so that whole-degree azimuths
+/// flickering across a boundary do not cut it into slivers.
+const SECTOR_HYSTERESIS: f64 = 1.0;
 
-    for (id, idxs) in by_id {
-        let mut current_arc_indices: Vec<usize> = Vec::new();
-        let mut arc_start_time: i64 = 0;
-        let mut last_time: i64 = 0;
+/// Whether `azimuth` lies within `sector` widened by `SECTOR_HYSTERESIS` on
+/// both sides, across north if need be.
+fn near_sector(sector: &AzimuthSector, azimuth: f64) -> bool {
+    let offset = (azimuth - sector.start).rem_euclid(360.0);
+    offset <= sector.end - sector.start + SECTOR_HYSTERESIS || offset >= 360.0 - SECTOR_HYSTERESIS
+}
+
+/// Splits a time-ordered run of samples wherever the satellite moves into
+/// another azimuth sector of `width` degrees.
+fn split_by_sector(samples: Vec<ArcSample>, width: f64) -> Vec<(AzimuthSector, Vec<ArcSample>)> {
+    let mut segments: Vec<(AzimuthSector, Vec<ArcSample>)> = Vec::new();
+    for sample in samples {
+        match segments.last_mut() {
+            Some((sector, segment)) if near_sector(sector, sample.azimuth) => segment.push(sample),
+            _ => {
+                let start = (sample.azimuth.rem_euclid(360.0) / width).floor() * width;
+                segments.push((AzimuthSector { start, end: (start + width).min(360.0) }, vec![sample]));
+            }
+        }
+    }
+    segments
+}
 
-        for &i in &idxs {
-            let t = records[i].time;
+/// Why a candidate arc was not accepted.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArcRejection {
+    TooFewPoints(usize),
+    /// Duration (s).
+    TooShort(f64),
+    /// Elevation swept (degrees).
+    ElevationSpanTooSmall(f64),
+    /// Change in elevation rate (degrees per second) and where it happened.
+    ElevationRateJump { jump: f64, time: Epoch },
+}
 
-            if current_arc_indices.is_empty() {
-                // start a new arc
-                current_arc_indices.push(i);
-                arc_start_time = t;
-                last_time = t;
+impl fmt::Display for ArcRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArcRejection::TooFewPoints(n) => write!(f, "too few points ({})", n),
+            ArcRejection::TooShort(duration) => write!(f, "too short ({:.0} s)", duration),
+            ArcRejection::ElevationSpanTooSmall(span) => write!(f, "elevation span too small ({:.1}°)", span),
+            ArcRejection::ElevationRateJump { jump, time } => write!(f, "elevation rate jumps by {:.4}°/s at {}", jump, time),
+        }
+    }
+}
+
+/// An arc that failed the acceptance criteria in `Config`, kept to show
+/// where coverage is missing.
+#[derive(Debug, Clone)]
+pub struct RejectedArc {
+    pub arc: Arc,
+    pub reason: ArcRejection,
+}
+
+/// Arcs found by `find_arcs`, accepted and rejected.
+#[derive(Debug, Clone, Default)]
+pub struct ArcLog {
+    pub arcs: Vec<Arc>,
+    pub rejected: Vec<RejectedArc>,
+}
+
+/// The first acceptance criterion in `config` the arc fails, if any.
+fn check_arc(arc: &Arc, config: &Config) -> Option<ArcRejection> {
+    let n = arc.samples.len();
+    if n < config.min_arc_points {
+        return Some(ArcRejection::TooFewPoints(n));
+    }
+    let duration = arc.time_end - arc.time_start;
+    if duration < config.min_arc_duration {
+        return Some(ArcRejection::TooShort(duration));
+    }
+    let elevations = arc.samples.iter().map(|sample| sample.elevation);
+    let (low, high) = elevations.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), e| (lo.min(e), hi.max(e)));
+    if high - low < config.min_elevation_span {
+        return Some(ArcRejection::ElevationSpanTooSmall(high - low));
+    }
+    if let Some(max_jump) = config.max_elevation_rate_jump {
+        let mut previous_rate: Option<f64> = None;
+        for pair in arc.samples.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            let dt = b.time - a.time;
+            if dt <= 0.0 {
                 continue;
             }
+            let rate = (b.elevation - a.elevation) / dt;
+            if let Some(previous) = previous_rate && (rate - previous).abs() > max_jump {
+                return Some(ArcRejection::ElevationRateJump { jump: (rate - previous).abs(), time: b.time });
+            }
+            previous_rate = Some(rate);
+        }
+    }
+    None
+}
+
+/// Splits each satellite's records into arcs: on time gaps, at elevation
+/// extrema into rising and setting arcs, and, when `config` asks for it, by
+/// azimuth sector. Arcs failing the acceptance criteria in `config` are
+/// returned apart with the reason.
+pub fn find_arcs(records: &VecDeque<Record>, config: &Config) -> ArcLog {
+    let mut log = ArcLog::default();
+    if records.is_empty() {
+        return log;
+    }
+
+    // Group records by ID, into runs within the elevation and azimuth window
+    let mut by_id: HashMap<u32, (Band, Vec<Vec<ArcSample>>)> = HashMap::new();
+    for rec in records {
+        if !passes_quality_checks(rec, config) {
+            continue;
+        }
+        let runs = &mut by_id.entry(rec.id).or_insert_with(|| (rec.band, vec![Vec::new()])).1;
+        if !config.accepts_geometry(rec.elevation, rec.azimuth) {
+            // Out of the window: the run so far is over.
+            if runs.last().is_some_and(|run| !run.is_empty()) {
+                runs.push(Vec::new());
+            }
+            continue;
+        }
+        if let Some(run) = runs.last_mut() {
+            run.push(ArcSample::from(rec));
+        }
+    }
+
+    for (id, (band, runs)) in by_id {
+        let mut passes: Vec<Vec<ArcSample>> = Vec::new();
+
+        for run in runs.into_iter().filter(|run| !run.is_empty()) {
+            let mut current_pass: Vec<ArcSample> = Vec::new();
+            for sample in run {
+                if let Some(last) = current_pass.last() && sample.time - last.time > config.max_arc_gap {
+                    passes.push(std::mem::take(&mut current_pass));
+                }
+                current_pass.push(sample);
+            }
+            passes.push(current_pass);
+        }
+
+        for pass in passes {
+            for (direction, segment) in split_by_direction(pass) {
+                add_segment(id, band, direction, segment, config, &mut log);
+            }
+        }
+    }
+
+    log
+}
+
+/// Turns a rising or setting segment into arcs, split by azimuth sector when
+/// `config` asks for it, and sorts them into accepted and rejected.
+fn add_segment(id: u32, band: Band, direction: ArcDirection, segment: Vec<ArcSample>, config: &Config, log: &mut ArcLog) {
+    let pieces = match config.azimuth_sector_width {
+        Some(width) => split_by_sector(segment, width).into_iter().map(|(sector, s)| (Some(sector), s)).collect(),
+        None => vec![(None, segment)],
+    };
+    for (sector, samples) in pieces {
+        let start = samples[0].time;
+        let end = samples[samples.len() - 1].time;
+        let arc = Arc::new(id, band, start, end, samples, direction, sector);
+        match check_arc(&arc, config) {
+            Some(reason) => log.rejected.push(RejectedArc { arc, reason }),
+            None => log.arcs.push(arc),
+        }
+    }
+}
+
+/// A satellite pass still being observed.
+#[derive(Debug, Clone)]
+struct OpenArc {
+    band: Band,
+    samples: Vec<ArcSample>,
+    splitter: DirectionSplitter,
+    last_time: Epoch,
+}
+
+/// Detects arcs incrementally in a live stream. Records are fed in one at a
+/// time, and an arc comes out as soon as it is complete: at an elevation
+/// extremum, when the satellite leaves the elevation window, or when it has
+/// not been seen for `max_arc_gap`. Arcs are split and checked as
+/// `find_arcs` would. Open arcs keep copies of their samples, so records
+/// may be evicted from the database once they have been pushed.
+pub struct ArcTracker {
+    config: Config,
+    open: HashMap<u32, OpenArc>,
+}
+
+impl ArcTracker {
+    pub fn new(config: &Config) -> Self {
+        ArcTracker {
+            config: config.clone(),
+            open: HashMap::new(),
+        }
+    }
+
+    /// Number of signals with an arc in progress.
+    pub fn open_arcs(&self) -> usize {
+        self.open.len()
+    }
 
-            if t - last_time > 120 && current_arc_indices.len() > 1 {
-                // finalize previous arc
-                let arc_indices = std::mem::take(&mut current_arc_indices);
-                println!("Adding arc for ID {}: {} records from {} to {}", id, arc_indices.len(), arc_start_time, last_time);
-                arcs.push(Arc::new(id, arc_start_time, last_time, arc_indices));
-
-                // start next arc
-                current_arc_indices.push(i);
-                arc_start_time = t;
-            } else {
-                current_arc_indices.push(i);
+    /// Feeds the next record; records must come in time order. Returns the
+    /// arcs this completed, including any that timed out by the record's time.
+    pub fn push(&mut self, rec: &Record) -> ArcLog {
+        let mut log = self.expire(rec.time);
+        if !passes_quality_checks(rec, &self.config) {
+            return log;
+        }
+        if !self.config.accepts_geometry(rec.elevation, rec.azimuth) {
+            // Out of the window: whatever was open for this signal is over.
+            if let Some(open) = self.open.remove(&rec.id) {
+                self.close(rec.id, open, &mut log);
             }
+            return log;
+        }
 
-            last_time = t;
+        let open = self.open.entry(rec.id).or_insert_with(|| OpenArc {
+            band: rec.band,
+            samples: Vec::new(),
+            splitter: DirectionSplitter::default(),
+            last_time: rec.time,
+        });
+        open.samples.push(ArcSample::from(rec));
+        open.last_time = rec.time;
+        if let Some((direction, range)) = open.splitter.push(rec.elevation) {
+            let segment = open.samples[range].to_vec();
+            add_segment(rec.id, open.band, direction, segment, &self.config, &mut log);
+            // Only the open segment is needed from here on.
+            let closed = open.splitter.drain_closed();
+            open.samples.drain(..closed);
         }
+        log
+    }
+
+    /// Closes every arc whose signal has not been seen for more than
+    /// `max_arc_gap` at time `now`. Call this when no records arrive, so
+    /// arcs still come out after the receiver loses a satellite.
+    pub fn expire(&mut self, now: Epoch) -> ArcLog {
+        let mut log = ArcLog::default();
+        let stale: Vec<u32> = self.open.iter().filter(|(_, open)| now - open.last_time > self.config.max_arc_gap).map(|(&id, _)| id).collect();
+        for id in stale {
+            if let Some(open) = self.open.remove(&id) {
+                self.close(id, open, &mut log);
+            }
+        }
+        log
+    }
 
-        // 4) Emit the final arc for this ID (if any).
-        if !current_arc_indices.is_empty() {
-            let arc_indices = std::mem::take(&mut current_arc_indices);
-            println!("Finalizing arc for ID {}: {} records from {} to {}", id, arc_indices.len(), arc_start_time, last_time);
-            arcs.push(Arc::new(id, arc_start_time, last_time, arc_indices));
+    /// Closes every open arc, e.g. at the end of a file.
+    pub fn finish(&mut self) -> ArcLog {
+        let mut log = ArcLog::default();
+        for (id, open) in std::mem::take(&mut self.open) {
+            self.close(id, open, &mut log);
         }
+        log
     }
 
-    arcs
+    fn close(&self, id: u32, open: OpenArc, log: &mut ArcLog) {
+        if let Some((direction, range)) = open.splitter.finish() {
+            add_segment(id, open.band, direction, open.samples[range].to_vec(), &self.config, log);
+        }
+    }
 }
 
 use polyfit_rs::polyfit_rs::polyfit;
 
-pub fn fix_arc_elev_azim(arc: &Arc, records: &mut VecDeque<Record>) {
-    let mut times = Vec::with_capacity(arc.record_indices.len());
-    let mut elevs = Vec::with_capacity(arc.record_indices.len());
-    let mut azims = Vec::with_capacity(arc.record_indices.len());
+/// Degree of the polynomials smoothing elevation and azimuth over an arc.
+const SMOOTHING_DEGREE: usize = 3;
+
+/// Largest accepted difference (degrees) between the smoothed geometry and
+/// the receiver's values. These come in whole degrees, and receivers do not
+/// all round the same way or update every epoch, so allow more than half a degree.
+const MAX_SMOOTHING_RESIDUAL: f64 = 1.5;
 
-    for &idx in &arc.record_indices {
-        if let Some(rec) = records.get(idx) {
-            times.push(rec.time as f64);
-            elevs.push(rec.elevation as f64);
-            azims.push(rec.azimuth as f64);
+/// Why a polynomial fit over an arc, smoothing its geometry or detrending
+/// its SNR, could not be used. The arc is left as it was.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SmoothingError {
+    TooFewPoints(usize),
+    FitFailed(&'static str),
+    /// The fitted curve strays from the receiver values by more than rounding explains.
+    ResidualTooLarge { quantity: &'static str, residual: f64 },
+}
+
+impl fmt::Display for SmoothingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SmoothingError::TooFewPoints(n) => write!(f, "too few points to fit ({})", n),
+            SmoothingError::FitFailed(reason) => write!(f, "polynomial fit failed: {}", reason),
+            SmoothingError::ResidualTooLarge { quantity, residual } => {
+                write!(f, "smoothed {} is {:.2} degrees off the receiver value", quantity, residual)
+            }
         }
     }
+}
+
+impl std::error::Error for SmoothingError {}
+
+fn eval_poly(coeffs: &[f64], x: f64) -> f64 {
+    coeffs.iter().rev().fold(0.0, |acc, &c| acc * x + c)
+}
 
-    // Fit 3rd order polynomials
-    let elev_poly = polyfit(&times, &elevs, 3).unwrap_or(vec![0.0; 4]);
-    let azim_poly = polyfit(&times, &azims, 3).unwrap_or(vec![0.0; 4]);
+/// Fits a polynomial of `degree` and returns the fitted values at `x`.
+fn fit_poly(x: &[f64], y: &[f64], degree: usize) -> Result<Vec<f64>, SmoothingError> {
+    let coeffs = polyfit(x, y, degree).map_err(SmoothingError::FitFailed)?;
+    if coeffs.len() != degree + 1 || coeffs.iter().any(|c| !c.is_finite()) {
+        return Err(SmoothingError::FitFailed("non-finite coefficients"));
+    }
+    Ok(x.iter().map(|&xi| eval_poly(&coeffs, xi)).collect())
+}
 
-    // Helper to evaluate polynomial
-    fn eval_poly(coeffs: &[f64], x: f64) -> f64 {
-        coeffs.iter().rev().fold(0.0, |acc, &c| acc * x + c)
+/// Fits a polynomial and returns the fitted values, or an error when the fit
+/// fails or does not stay within `MAX_SMOOTHING_RESIDUAL` of the data.
+fn smooth(x: &[f64], y: &[f64], quantity: &'static str) -> Result<Vec<f64>, SmoothingError> {
+    let fitted = fit_poly(x, y, SMOOTHING_DEGREE)?;
+    let residual = fitted.iter().zip(y).map(|(f, v)| (f - v).abs()).fold(0.0, f64::max);
+    if residual > MAX_SMOOTHING_RESIDUAL {
+        return Err(SmoothingError::ResidualTooLarge { quantity, residual });
     }
+    Ok(fitted)
+}
+
+/// Replaces the whole-degree elevation and azimuth of the arc's records by
+/// cubic fits over time. Time is centred and scaled to [-1, 1] so the fit is
+/// well conditioned, and azimuth is unwrapped so arcs crossing north fit
+/// too. Nothing is written unless both fits succeed.
+pub fn fix_arc_elev_azim(arc: &mut Arc) -> Result<(), SmoothingError> {
+    let mut positions = Vec::with_capacity(arc.samples.len());
+    let mut times = Vec::with_capacity(arc.samples.len());
+    let mut elevs = Vec::with_capacity(arc.samples.len());
+    let mut azims: Vec<f64> = Vec::with_capacity(arc.samples.len());
 
-    // Write interpolated values back
-    for (&idx, &t) in arc.record_indices.iter().zip(times.iter()) {
-        if let Some(rec) = records.get_mut(idx) {
-            let new_elev = eval_poly(&elev_poly, t);
-            let new_azim = eval_poly(&azim_poly, t);
-            //println!("Arc ID {}: Updating record at time {}: elev {:.2} -> {:.2}, azim {:.2} -> {:.2}", arc.sat_id, t as i64, rec.elevation, new_elev, rec.azimuth, new_azim);
-            rec.elevation = new_elev;
-            rec.azimuth = new_azim;
+    for (k, sample) in arc.samples.iter().enumerate() {
+        if sample.elevation.is_finite() && sample.azimuth.is_finite() {
+            positions.push(k);
+            times.push(sample.time.gps_seconds());
+            elevs.push(sample.elevation);
+            // Keep each azimuth within 180 degrees of the previous one.
+            let azimuth = match azims.last() {
+                Some(&previous) => previous + (sample.azimuth - previous + 180.0).rem_euclid(360.0) - 180.0,
+                None => sample.azimuth,
+            };
+            azims.push(azimuth);
         }
     }
+    if positions.len() <= SMOOTHING_DEGREE + 1 {
+        return Err(SmoothingError::TooFewPoints(positions.len()));
+    }
+
+    let t_min = times.iter().cloned().fold(f64::INFINITY, f64::min);
+    let t_max = times.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let centre = (t_min + t_max) / 2.0;
+    let half_span = ((t_max - t_min) / 2.0).max(1.0);
+    let x: Vec<f64> = times.iter().map(|t| (t - centre) / half_span).collect();
+
+    let new_elevs = smooth(&x, &elevs, "elevation")?;
+    let new_azims = smooth(&x, &azims, "azimuth")?;
+
+    for ((&k, elev), azim) in positions.iter().zip(new_elevs).zip(new_azims) {
+        let sample = &mut arc.samples[k];
+        sample.elevation = elev;
+        sample.azimuth = azim.rem_euclid(360.0);
+    }
+    Ok(())
 }
 
-pub fn correct_arc_snr(arc: &Arc, records: &mut VecDeque<Record>) {
-    // Collect SNR values for the arc's records
-    // let snr_values: Vec<f64> = arc
-    //     .record_indices
-    //     .iter()
-    //     .filter_map(|&idx| records.get(idx).map(|rec| rec.snr))
-    //     .collect();
+/// Replaces the geometric elevation of the arc's samples by the apparent
+/// elevation the signals arrive at. Apply once, after any smoothing.
+pub fn correct_arc_refraction(arc: &mut Arc, model: RefractionModel, weather: &Weather) {
+    if model == RefractionModel::None {
+        return;
+    }
+    for sample in &mut arc.samples {
+        sample.elevation = model.apparent_elevation(sample.elevation, weather);
+    }
+}
 
-    // Detrend SNR using a 3rd order polynomial
-    // let detrended = match scirs2_signal::detrend::detrend_poly(&snr_values, 3) {
-    //     Ok(d) => d,
-    //     Err(e) => {
-    //         eprintln!("Detrending error for arc with sat_id {}: {}", arc.sat_id, e);
-    //         return;
-    //     }
-    // };
+/// Prepares the arc's SNR for spectral analysis, as GNSS-IR usually does:
+/// converts dB-Hz to a linear amplitude and removes the direct signal with a
+/// polynomial of `degree` in the sine of elevation, leaving the reflected
+/// signal's oscillation in `arc.detrended_snr`. The raw values stay in the
+/// samples. Samples without a finite elevation or SNR get NaN.
+pub fn correct_arc_snr(arc: &mut Arc, degree: usize) -> Result<(), SmoothingError> {
+    let (positions, (x, linear)): (Vec<usize>, (Vec<f64>, Vec<f64>)) = arc
+        .samples
+        .iter()
+        .enumerate()
+        .filter(|(_, sample)| sample.elevation.is_finite() && sample.snr.is_finite())
+        .map(|(k, sample)| (k, (sample.elevation.to_radians().sin(), 10f64.powf(sample.snr / 20.0))))
+        .unzip();
+    if positions.len() <= degree + 1 {
+        return Err(SmoothingError::TooFewPoints(positions.len()));
+    }
 
-    // Write detrended SNR back to the records
-    // for (i, &idx) in arc.record_indices.iter().enumerate() {
-    //     if let Some(rec) = records.get_mut(idx) {
-    //         rec.snr = detrended[i];
-    //     }
-    // }
+    // Scale sin(elevation) to [-1, 1] so the fit is well conditioned.
+    let x_min = x.iter().cloned().fold(f64::INFINITY, f64::min);
+    let x_max = x.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let centre = (x_min + x_max) / 2.0;
+    let half_span = (x_max - x_min) / 2.0;
+    if half_span <= 0.0 {
+        return Err(SmoothingError::FitFailed("elevation does not change"));
+    }
+    let scaled: Vec<f64> = x.iter().map(|xi| (xi - centre) / half_span).collect();
+    let trend = fit_poly(&scaled, &linear, degree)?;
+
+    let mut detrended = vec![f64::NAN; arc.samples.len()];
+    for ((&k, value), fitted) in positions.iter().zip(linear).zip(trend) {
+        detrended[k] = value - fitted;
+    }
+    arc.detrended_snr = detrended;
+    Ok(())
 }
 
 pub fn lin_range(start: f64, stop: f64, step_size: f64) -> Vec<f64> {
@@ -134,19 +579,37 @@ pub fn lin_range(start: f64, stop: f64, step_size: f64) -> Vec<f64> {
     values
 }
 
-pub fn find_arc_frequencies(arc: &Arc, records: &VecDeque<Record>, config: &Config) -> Vec<(f64, f64)> {
-    let n = arc.record_indices.len();
+pub fn find_arc_frequencies(arc: &Arc, config: &Config) -> Vec<(f64, f64)> {
+    // Arcs from `find_arcs` have passed `min_arc_points`; this only guards the fit.
+    let n = arc.samples.len();
     if n < 3 {
         eprintln!("Arc {}: too few points (n={}), skipping.", arc.sat_id, n);
         return Vec::new();
     }
     let steps = lin_range(config.min_height, config.max_height, config.step_size);
 
-    let l1_wv_m = 299_792_458.0 / (1575.42e6); // L1 wavelength (m)
-    let cf = l1_wv_m / 2.0;
+    let wavelength = match arc.band.wavelength() {
+        Some(w) => w,
+        None => {
+            eprintln!("Arc {}: unknown band, skipping.", arc.sat_id);
+            return Vec::new();
+        }
+    };
+    let cf = wavelength / 2.0;
 
-    let arc_records: Vec<&Record> = arc.record_indices.iter().filter_map(|&idx| records.get(idx)).collect();
-    let mut pairs: Vec<(f64, f64)> = arc_records.iter().map(|rec| ((rec.elevation.to_radians()).sin() / cf, rec.snr)).collect();
+    // The detrended series once `correct_arc_snr` has run, the raw dB-Hz values otherwise.
+    let snr: Vec<f64> = if arc.detrended_snr.len() == arc.samples.len() {
+        arc.detrended_snr.clone()
+    } else {
+        arc.samples.iter().map(|sample| sample.snr).collect()
+    };
+    let mut pairs: Vec<(f64, f64)> = arc
+        .samples
+        .iter()
+        .zip(snr)
+        .map(|(sample, snr)| ((sample.elevation.to_radians()).sin() / cf, snr))
+        .filter(|(x, y)| x.is_finite() && y.is_finite())
+        .collect();
 
     // Sort pairs by elevation
     pairs.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
@@ -159,9 +622,313 @@ pub fn find_arc_frequencies(arc: &Arc, records: &VecDeque<Record>, config: &Conf
         &steps,
     );
 
-    steps.into_iter().zip(power.into_iter()).collect()
+    steps.into_iter().zip(power).collect()
 }
 
-pub fn find_max_amplitude_frequency(frequencies: &Vec<(f64, f64)>) -> Option<(f64, f64)> {
+pub fn find_max_amplitude_frequency(frequencies: &[(f64, f64)]) -> Option<(f64, f64)> {
     frequencies.iter().cloned().max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
-}
\ No newline at end of file
+}
+
+/// Takes an arc through geometry smoothing, refraction correction and SNR
+/// correction, ready for spectral analysis.
+pub fn prepare_arc(arc: &mut Arc, weather: &Weather, config: &Config) {
+    // Geometry computed from orbits is exact; only the receiver's whole degrees need smoothing.
+    if config.orbits == OrbitSource::Receiver && let Err(e) = fix_arc_elev_azim(arc) {
+        eprintln!("Arc {}: geometry left unsmoothed, {}", arc.sat_id, e);
+    }
+    correct_arc_refraction(arc, config.refraction, weather);
+    if let Err(e) = correct_arc_snr(arc, config.snr_detrend_degree) {
+        eprintln!("Arc {}: SNR left undetrended, {}", arc.sat_id, e);
+    }
+}
+
+/// Prepares an arc and returns its reflector height (m) with the
+/// Lomb–Scargle amplitude there.
+pub fn process_arc(arc: &mut Arc, weather: &Weather, config: &Config) -> Option<(f64, f64)> {
+    prepare_arc(arc, weather, config);
+    find_max_amplitude_frequency(&find_arc_frequencies(arc, config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::record::{test_record, Network, RecordDatabase};
+
+    const START: i64 = 1_700_000_000_000;
+
+    fn record(satellite: u32, second: i64, elevation: f64, azimuth: f64) -> Record {
+        Record {
+            elevation,
+            azimuth,
+            snr: 40.0 + (second % 7) as f64,
+            ..test_record(Network::GPS, satellite, Epoch::from_unix_utc_millis(START + second * 1000))
+        }
+    }
+
+    /// G01 rises through the window for 600 s; G02 sets through it from
+    /// 200 s to 900 s. One record per satellite per second, in time order.
+    fn two_passes() -> Vec<Record> {
+        let mut records = Vec::new();
+        for second in 0..900 {
+            if second < 600 {
+                records.push(record(1, second, 1.5 + 8.0 * second as f64 / 600.0, 100.0 + 0.05 * second as f64));
+            }
+            if second >= 200 {
+                records.push(record(2, second, 9.5 - 8.0 * (second - 200) as f64 / 700.0, 250.0));
+            }
+        }
+        records
+    }
+
+    fn samples_of(records: &[Record], satellite: u32) -> Vec<ArcSample> {
+        records.iter().filter(|rec| rec.satellite == satellite).map(ArcSample::from).collect()
+    }
+
+    fn sorted(mut arcs: Vec<Arc>) -> Vec<Arc> {
+        arcs.sort_by_key(|arc| arc.sat_id);
+        arcs
+    }
+
+    #[test]
+    fn tracker_arcs_survive_eviction() {
+        let records = two_passes();
+        let config = Config::default();
+        let mut tracker = ArcTracker::new(&config);
+        let mut record_db = RecordDatabase::new();
+        let mut arcs = Vec::new();
+
+        for batch in records.chunks(30) {
+            for rec in batch {
+                arcs.extend(tracker.push(rec).arcs);
+            }
+            record_db.insert_many(batch.to_vec());
+            let last = record_db.records.back().unwrap().time;
+            record_db.evict_before(last - 60.0);
+            assert!(record_db.records.front().unwrap().time >= last - 60.0);
+        }
+        arcs.extend(tracker.finish().arcs);
+
+        // The database only holds the last minute, yet every arc is complete.
+        let arcs = sorted(arcs);
+        assert_eq!(arcs.len(), 2);
+        assert_eq!(arcs[0].direction, ArcDirection::Rising);
+        assert_eq!(arcs[0].samples, samples_of(&records, 1));
+        assert_eq!(arcs[1].direction, ArcDirection::Setting);
+        assert_eq!(arcs[1].samples, samples_of(&records, 2));
+        assert_eq!(arcs[1].time_start, records.iter().find(|rec| rec.satellite == 2).unwrap().time);
+    }
+
+    #[test]
+    fn tracker_emits_arc_once_gap_expires() {
+        let records = two_passes();
+        let mut tracker = ArcTracker::new(&Config::default());
+        let mut emitted_at = None;
+        for rec in &records {
+            let log = tracker.push(rec);
+            if log.arcs.iter().any(|arc| arc.sat_id == records[0].id) {
+                emitted_at = Some(rec.time);
+            }
+        }
+        // G01 was last seen at 599 s and is closed by the first record more than `max_arc_gap` later.
+        let last_seen = records.iter().rfind(|rec| rec.satellite == 1).unwrap().time;
+        let emitted_at = emitted_at.expect("G01 arc was not emitted before the end of the stream");
+        assert!(emitted_at - last_seen > Config::default().max_arc_gap);
+        assert!(emitted_at - last_seen <= Config::default().max_arc_gap + 1.0);
+        assert_eq!(tracker.open_arcs(), 1);
+    }
+
+    #[test]
+    fn tracker_forgets_emitted_samples() {
+        // A satellite bobbing between 2 and 8 degrees every 200 s for an hour,
+        // giving a segment every 100 s.
+        let mut tracker = ArcTracker::new(&Config::default());
+        let mut emitted = 0;
+        for second in 0..3600 {
+            let phase = (second % 200) as f64 / 100.0;
+            let elevation = 2.0 + 6.0 * if phase < 1.0 { phase } else { 2.0 - phase };
+            let log = tracker.push(&record(1, second, elevation, 100.0));
+            emitted += log.arcs.len() + log.rejected.len();
+            let open = &tracker.open[&Record::make_id(Network::GPS, Band::L1, 1)];
+            assert_eq!(open.samples.len(), open.splitter.elevations.len());
+            assert!(open.samples.len() <= 200, "{} samples held at {} s", open.samples.len(), second);
+        }
+        assert!(emitted >= 30);
+        let log = tracker.finish();
+        assert_eq!(log.arcs.len() + log.rejected.len(), 1);
+    }
+
+    #[test]
+    fn batch_arcs_survive_eviction() {
+        let records = two_passes();
+        let config = Config::default();
+        let mut record_db = RecordDatabase::new();
+        record_db.insert_many(records.clone());
+        let mut arcs = sorted(find_arcs(&record_db.records, &config).arcs);
+
+        // Slide the window past both passes and refill it with another satellite.
+        record_db.evict_before(records[records.len() - 1].time + 1.0);
+        assert!(record_db.records.is_empty());
+        record_db.insert_many((900..1500).map(|second| record(3, second, 9.0 - (second - 900) as f64 / 100.0, 10.0)).collect());
+
+        // Processing the arcs now gives what it gives on records never evicted.
+        let mut expected = sorted(find_arcs(&records.iter().cloned().collect(), &config).arcs);
+        for arc in arcs.iter_mut().chain(expected.iter_mut()) {
+            fix_arc_elev_azim(arc).unwrap();
+            correct_arc_snr(arc, config.snr_detrend_degree).unwrap();
+        }
+        assert_eq!(arcs.len(), 2);
+        for (arc, expected) in arcs.iter().zip(&expected) {
+            assert_eq!(arc.samples, expected.samples);
+            assert_eq!(arc.detrended_snr, expected.detrended_snr);
+            assert!(arc.detrended_snr.iter().all(|value| value.is_finite()));
+            assert_eq!(find_arc_frequencies(arc, &config), find_arc_frequencies(expected, &config));
+        }
+        // The raw SNR is still that of the records the arcs were found in.
+        let snr: Vec<f64> = arcs[0].samples.iter().map(|sample| sample.snr).collect();
+        assert_eq!(snr, samples_of(&records, 1).iter().map(|sample| sample.snr).collect::<Vec<f64>>());
+    }
+
+    #[test]
+    fn find_arcs_keeps_to_the_window() {
+        let records: VecDeque<Record> = two_passes().into();
+        assert_eq!(find_arcs(&records, &Config::default()).arcs.len(), 2);
+
+        // Mask the start of G01 by azimuth and the top of G02 by elevation.
+        let config = Config { min_azimuth: 110.0, max_elevation: 8.0, ..Config::default() };
+        let arcs = sorted(find_arcs(&records, &config).arcs);
+        assert_eq!(arcs.len(), 2);
+        for arc in &arcs {
+            assert!(arc.samples.iter().all(|sample| config.accepts_geometry(sample.elevation, sample.azimuth)));
+        }
+        assert_eq!(arcs[0].time_start, Epoch::from_unix_utc_millis(START + 200_000));
+
+        // G01 briefly leaves the window mid-pass, which ends its arc.
+        let records: VecDeque<Record> = two_passes().into_iter().filter(|rec| rec.satellite == 1).map(|mut rec| {
+            let second = rec.time - Epoch::from_unix_utc_millis(START);
+            if (300.0..305.0).contains(&second) {
+                rec.elevation = 0.5;
+            }
+            rec
+        }).collect();
+        let mut arcs = find_arcs(&records, &Config::default()).arcs;
+        arcs.sort_by_key(|arc| arc.time_start);
+        assert_eq!(arcs.len(), 2);
+        assert_eq!(arcs[1].time_start, Epoch::from_unix_utc_millis(START + 305_000));
+    }
+
+    fn assert_tracker_matches_find_arcs(records: &[Record], config: &Config) {
+        let mut tracker = ArcTracker::new(config);
+        let mut arcs = Vec::new();
+        for rec in records {
+            arcs.extend(tracker.push(rec).arcs);
+        }
+        arcs.extend(tracker.finish().arcs);
+
+        let batch = sorted(find_arcs(&records.iter().cloned().collect(), config).arcs);
+        let live = sorted(arcs);
+        assert_eq!(live.len(), batch.len());
+        for (a, b) in live.iter().zip(&batch) {
+            assert_eq!((a.sat_id, a.direction, a.time_start, a.time_end), (b.sat_id, b.direction, b.time_start, b.time_end));
+            assert_eq!(a.samples, b.samples);
+        }
+    }
+
+    #[test]
+    fn tracker_matches_find_arcs() {
+        let records = two_passes();
+        assert_tracker_matches_find_arcs(&records, &Config::default());
+        // Masked by azimuth and elevation, the tracker cuts the same arcs.
+        let config = Config { min_azimuth: 110.0, max_elevation: 8.0, ..Config::default() };
+        assert_tracker_matches_find_arcs(&records, &config);
+    }
+
+    fn arc_of(records: &[Record]) -> Arc {
+        let samples: Vec<ArcSample> = records.iter().map(ArcSample::from).collect();
+        let (start, end) = (samples[0].time, samples[samples.len() - 1].time);
+        Arc::new(records[0].id, Band::L1, start, end, samples, ArcDirection::Rising, None)
+    }
+
+    #[test]
+    fn smoothing_undoes_whole_degree_rounding() {
+        // A slightly curved rise crossing north, reported in whole degrees.
+        let truth = |second: i64| {
+            let t = second as f64 / 600.0;
+            (2.0 + 7.0 * t - 1.5 * t * t, 350.0 + 20.0 * t)
+        };
+        let records: Vec<Record> = (0..600)
+            .map(|second| {
+                let (elevation, azimuth) = truth(second);
+                record(1, second, elevation.round(), azimuth.round().rem_euclid(360.0))
+            })
+            .collect();
+        let mut arc = arc_of(&records);
+        fix_arc_elev_azim(&mut arc).unwrap();
+
+        for (second, sample) in (0..600).zip(&arc.samples) {
+            let (elevation, azimuth) = truth(second);
+            assert!((sample.elevation - elevation).abs() < 0.3, "elevation {} at {} s", sample.elevation, second);
+            let azimuth_error = (sample.azimuth - azimuth + 180.0).rem_euclid(360.0) - 180.0;
+            assert!(azimuth_error.abs() < 0.3, "azimuth {} at {} s", sample.azimuth, second);
+            assert!((0.0..360.0).contains(&sample.azimuth));
+        }
+    }
+
+    #[test]
+    fn smoothing_leaves_unfit_arcs_alone() {
+        let few: Vec<Record> = (0..4).map(|second| record(1, second, 5.0, 100.0)).collect();
+        let mut arc = arc_of(&few);
+        assert_eq!(fix_arc_elev_azim(&mut arc), Err(SmoothingError::TooFewPoints(4)));
+
+        // A 5 degree step is no rounding error.
+        let stepped: Vec<Record> = (0..100).map(|second| record(1, second, if second < 50 { 3.0 } else { 8.0 }, 100.0)).collect();
+        let mut arc = arc_of(&stepped);
+        let before = arc.samples.clone();
+        assert!(matches!(fix_arc_elev_azim(&mut arc), Err(SmoothingError::ResidualTooLarge { quantity: "elevation", .. })));
+        assert_eq!(arc.samples, before);
+    }
+
+    #[test]
+    fn sectors_ignore_flicker_at_a_boundary() {
+        // Whole-degree azimuths wobble across 90 before moving on for good.
+        let azimuths = [86.0, 87.0, 88.0, 89.0, 90.0, 89.0, 90.0, 89.0, 90.0, 91.0, 90.0, 91.0, 92.0, 93.0, 94.0];
+        let samples: Vec<ArcSample> = azimuths.iter().enumerate().map(|(k, &az)| ArcSample::from(&record(1, k as i64, 5.0, az))).collect();
+        let segments = split_by_sector(samples, 90.0);
+        let split: Vec<(f64, usize)> = segments.iter().map(|(sector, segment)| (sector.start, segment.len())).collect();
+        assert_eq!(split, vec![(0.0, 12), (90.0, 3)]);
+
+        // The same holds across north.
+        let azimuths = [357.0, 358.0, 359.0, 0.0, 359.0, 0.0, 1.0, 2.0, 3.0];
+        let samples: Vec<ArcSample> = azimuths.iter().enumerate().map(|(k, &az)| ArcSample::from(&record(1, k as i64, 5.0, az))).collect();
+        let split: Vec<(f64, usize)> = split_by_sector(samples, 90.0).iter().map(|(sector, segment)| (sector.start, segment.len())).collect();
+        assert_eq!(split, vec![(270.0, 7), (0.0, 2)]);
+    }
+
+    #[test]
+    fn detrended_snr_gives_reflector_height() {
+        // A 12 m reflector on L1: a direct signal rising with elevation plus
+        // an interference term oscillating in sin(elevation).
+        let height = 12.0;
+        let wavelength = Band::L1.wavelength().unwrap();
+        let records: Vec<Record> = (0..1200)
+            .map(|second| {
+                let elevation = 2.0 + 8.0 * second as f64 / 1200.0;
+                let x = elevation.to_radians().sin();
+                let amplitude = 100.0 + 300.0 * x + 20.0 * (4.0 * std::f64::consts::PI * height * x / wavelength).cos();
+                Record { snr: 20.0 * amplitude.log10(), ..record(1, second, elevation, 100.0) }
+            })
+            .collect();
+        let mut arc = arc_of(&records);
+        let config = Config::default();
+        correct_arc_snr(&mut arc, config.snr_detrend_degree).unwrap();
+
+        // The raw values stay; the detrended series is the oscillation alone.
+        assert_eq!(arc.samples, records.iter().map(ArcSample::from).collect::<Vec<_>>());
+        assert_eq!(arc.detrended_snr.len(), records.len());
+        let mean = arc.detrended_snr.iter().sum::<f64>() / records.len() as f64;
+        assert!(mean.abs() < 1.0, "mean {}", mean);
+        assert!(arc.detrended_snr.iter().all(|value| value.abs() < 25.0));
+
+        let (found, _) = find_max_amplitude_frequency(&find_arc_frequencies(&arc, &config)).unwrap();
+        assert!((found - height).abs() <= config.step_size, "height {}", found);
+    }
+}
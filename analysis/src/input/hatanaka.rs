@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read};
+
+use crate::rinex::RinexHeader;
+
+/// Label of the first line of a compact RINEX file.
+pub const CRINEX_LABEL: &str = "CRINEX VERS   / TYPE";
+
+/// Columns of the epoch line before the satellite list, RINEX 2 and 3.
+const EPOCH_LENGTH_2: usize = 32;
+const EPOCH_LENGTH_3: usize = 41;
+
+const SATELLITES_PER_LINE_2: usize = 12;
+const OBSERVATIONS_PER_LINE_2: usize = 5;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("compact RINEX: {}", message))
+}
+
+/// Applies a compact RINEX text difference: a space keeps the old
+/// character, `&` stands for a space and anything else replaces it.
+fn repair(old: &mut Vec<u8>, diff: &[u8]) {
+    for (i, &c) in diff.iter().enumerate() {
+        let new = match c {
+            b' ' if i < old.len() => continue,
+            b'&' => b' ',
+            c => c,
+        };
+        if i < old.len() {
+            old[i] = new;
+        } else {
+            old.push(new);
+        }
+    }
+}
+
+/// One differenced quantity: the value and its differences up to the order
+/// of the arc, all as integers in the last digit of the RINEX format.
+#[derive(Debug, Clone, Default)]
+struct DiffState {
+    arc_order: usize,
+    order: usize,
+    differences: Vec<i64>,
+}
+
+impl DiffState {
+    /// Parses a field, `n&value` to start an arc of order n or a difference
+    /// of the current order. Returns the restored value.
+    fn push(state: &mut Option<DiffState>, field: &str) -> io::Result<i64> {
+        if let Some((order, value)) = field.split_once('&') {
+            let arc_order = order.parse::<usize>().map_err(|_| invalid("bad arc order"))?;
+            let value = value.parse::<i64>().map_err(|_| invalid("bad value"))?;
+            let mut differences = vec![0; arc_order + 1];
+            differences[0] = value;
+            *state = Some(DiffState { arc_order, order: 0, differences });
+            return Ok(value);
+        }
+
+        let s = state.as_mut().ok_or_else(|| invalid("difference without initialisation"))?;
+        let difference = field.parse::<i64>().map_err(|_| invalid("bad difference"))?;
+        if s.order < s.arc_order {
+            s.order += 1;
+        }
+        s.differences[s.order] = difference;
+        for i in (0..s.order).rev() {
+            s.differences[i] += s.differences[i + 1];
+        }
+        Ok(s.differences[0])
+    }
+}
+
+#[derive(Default)]
+struct SatelliteState {
+    values: Vec<Option<DiffState>>,
+    flags: Vec<u8>,
+}
+
+/// Formats an integer in thousandths as F14.3.
+fn format_observation(value: i64) -> String {
+    let sign = if value < 0 { "-" } else { "" };
+    let abs = value.unsigned_abs();
+    format!("{:>14}", format!("{}{}.{:03}", sign, abs / 1000, abs % 1000))
+}
+
+/// Formats the receiver clock offset, in units of the last digit, as F12.9
+/// (RINEX 2) or F15.12 (RINEX 3).
+fn format_clock(value: i64, version_3: bool) -> String {
+    let (width, decimals) = if version_3 { (15, 12) } else { (12, 9) };
+    let scale = 10u64.pow(decimals);
+    let sign = if value < 0 { "-" } else { "" };
+    let abs = value.unsigned_abs();
+    let text = format!("{}{}.{:0w$}", sign, abs / scale, abs % scale, w = decimals as usize);
+    format!("{:>width$}", text, width = width)
+}
+
+/// Streaming decoder of Hatanaka compact RINEX (CRX 1.0 for RINEX 2, 3.0 for
+/// RINEX 3) back into plain RINEX observation text.
+pub struct HatanakaDecoder<R: BufRead> {
+    reader: R,
+    header: RinexHeader,
+    in_header: bool,
+    lines_read: usize,
+    epoch: Vec<u8>,
+    clock: Option<DiffState>,
+    satellites: HashMap<String, SatelliteState>,
+    line: String,
+    output: Vec<u8>,
+    position: usize,
+    done: bool,
+}
+
+impl<R: BufRead> HatanakaDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        HatanakaDecoder {
+            reader,
+            header: RinexHeader::default(),
+            in_header: true,
+            lines_read: 0,
+            epoch: Vec::new(),
+            clock: None,
+            satellites: HashMap::new(),
+            line: String::new(),
+            output: Vec::new(),
+            position: 0,
+            done: false,
+        }
+    }
+
+    /// Reads the next line into `self.line` without its line ending. False at
+    /// the end of the input.
+    fn next_line(&mut self) -> io::Result<bool> {
+        self.line.clear();
+        if self.reader.read_line(&mut self.line)? == 0 {
+            return Ok(false);
+        }
+        let trimmed = self.line.trim_end_matches(['\n', '\r']).len();
+        self.line.truncate(trimmed);
+        self.lines_read += 1;
+        Ok(true)
+    }
+
+    fn emit(&mut self, line: &str) {
+        self.output.extend_from_slice(line.trim_end().as_bytes());
+        self.output.push(b'\n');
+    }
+
+    fn version_3(&self) -> bool {
+        self.header.version >= 3.0
+    }
+
+    /// Copies the header, leaving out the two compact RINEX lines in front.
+    fn decode_header_line(&mut self) -> io::Result<()> {
+        if self.lines_read == 1 {
+            if self.line.get(60..).is_none_or(|label| label.trim_end() != CRINEX_LABEL) {
+                return Err(invalid("missing CRINEX VERS / TYPE"));
+            }
+            return Ok(());
+        }
+        if self.lines_read == 2 {
+            return Ok(());
+        }
+
+        let line = std::mem::take(&mut self.line);
+        match self.header.push_line(&line) {
+            Ok(end) => self.in_header = !end,
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        }
+        self.emit(&line);
+        self.line = line;
+        Ok(())
+    }
+
+    /// Decodes one epoch: the epoch line, the clock line and one line per satellite.
+    fn decode_epoch(&mut self) -> io::Result<()> {
+        let version_3 = self.version_3();
+        let line = std::mem::take(&mut self.line);
+        let init = if version_3 { '>' } else { '&' };
+        if line.starts_with(init) {
+            self.epoch.clear();
+            repair(&mut self.epoch, line.replacen('&', " ", 1).as_bytes());
+        } else {
+            repair(&mut self.epoch, line.as_bytes());
+        }
+        self.line = line;
+
+        let epoch = String::from_utf8_lossy(&self.epoch).into_owned();
+        let epoch_length = if version_3 { EPOCH_LENGTH_3 } else { EPOCH_LENGTH_2 };
+        let flag_column = if version_3 { 31 } else { 28 };
+        let flag = epoch.get(flag_column..flag_column + 1).and_then(|f| f.trim().parse::<u8>().ok())
+            .ok_or_else(|| invalid("bad epoch line"))?;
+        let count = epoch.get(flag_column + 1..flag_column + 4).and_then(|c| c.trim().parse::<usize>().ok())
+            .ok_or_else(|| invalid("bad epoch line"))?;
+
+        // Event records are stored as they are, followed by `count` special lines.
+        if flag > 1 {
+            let head = epoch.get(..flag_column + 4).unwrap_or(&epoch).to_string();
+            self.emit(&head);
+            for _ in 0..count {
+                if !self.next_line()? {
+                    return Err(invalid("truncated event record"));
+                }
+                let line = std::mem::take(&mut self.line);
+                self.emit(&line);
+                self.line = line;
+            }
+            return Ok(());
+        }
+
+        let list = epoch.get(epoch_length..).unwrap_or("");
+        let satellites: Vec<String> = (0..count)
+            .map(|i| list.get(3 * i..3 * i + 3).map(|s| s.to_string()).ok_or_else(|| invalid("short satellite list")))
+            .collect::<io::Result<_>>()?;
+        // A satellite that reappears starts over, its values and flags
+        // differenced against nothing rather than its last epoch.
+        self.satellites.retain(|satellite, _| satellites.contains(satellite));
+
+        if !self.next_line()? {
+            return Err(invalid("missing clock line"));
+        }
+        let clock = if self.line.trim().is_empty() {
+            self.clock = None;
+            None
+        } else {
+            let field = self.line.trim().to_string();
+            Some(DiffState::push(&mut self.clock, &field)?)
+        };
+
+        self.emit_epoch_line(&epoch[..epoch_length.min(epoch.len())], &satellites, clock);
+
+        for satellite in &satellites {
+            if !self.next_line()? {
+                return Err(invalid("truncated epoch"));
+            }
+            let line = std::mem::take(&mut self.line);
+            let decoded = self.decode_satellite(satellite, &line)?;
+            for out in decoded {
+                self.emit(&out);
+            }
+            self.line = line;
+        }
+        Ok(())
+    }
+
+    fn emit_epoch_line(&mut self, epoch: &str, satellites: &[String], clock: Option<i64>) {
+        let version_3 = self.version_3();
+        if version_3 {
+            let mut line = format!("{:<35}", epoch.get(..35).unwrap_or(epoch));
+            if let Some(clock) = clock {
+                line.push_str(&format!("      {}", format_clock(clock, true)));
+            }
+            self.emit(&line);
+            return;
+        }
+
+        let mut chunks = satellites.chunks(SATELLITES_PER_LINE_2);
+        let mut line = format!("{:<32}{}", epoch, chunks.next().map(|c| c.concat()).unwrap_or_default());
+        if let Some(clock) = clock {
+            line = format!("{:<68}{}", line, format_clock(clock, false));
+        }
+        self.emit(&line);
+        for chunk in chunks {
+            let line = format!("{:32}{}", "", chunk.concat());
+            self.emit(&line);
+        }
+    }
+
+    /// Restores the observations of one satellite and returns its RINEX lines.
+    fn decode_satellite(&mut self, satellite: &str, line: &str) -> io::Result<Vec<String>> {
+        let version_3 = self.version_3();
+        let system = satellite.chars().next().map(|c| if c == ' ' { 'G' } else { c }).unwrap_or('G');
+        let n_codes = self.header.observation_codes(system).map_or(0, |codes| codes.len());
+
+        let state = self.satellites.entry(satellite.to_string()).or_default();
+        state.values.resize(n_codes, None);
+
+        let mut values = Vec::with_capacity(n_codes);
+        let mut rest = line;
+        for value_state in state.values.iter_mut() {
+            let (field, remainder) = match rest.split_once(' ') {
+                Some((field, remainder)) => (field, Some(remainder)),
+                None => (rest, None),
+            };
+            if field.is_empty() {
+                // A blank field ends the arc of this observable.
+                *value_state = None;
+                values.push(None);
+            } else {
+                values.push(Some(DiffState::push(value_state, field)?));
+            }
+            rest = remainder.unwrap_or("");
+        }
+        repair(&mut state.flags, rest.as_bytes());
+
+        let fields: Vec<String> = values.iter().enumerate()
+            .map(|(i, value)| {
+                let lli = state.flags.get(2 * i).copied().unwrap_or(b' ') as char;
+                let ssi = state.flags.get(2 * i + 1).copied().unwrap_or(b' ') as char;
+                match value {
+                    Some(v) => format!("{}{}{}", format_observation(*v), lli, ssi),
+                    None => format!("{:16}", ""),
+                }
+            })
+            .collect();
+
+        if version_3 {
+            return Ok(vec![format!("{}{}", satellite, fields.concat())]);
+        }
+        Ok(fields.chunks(OBSERVATIONS_PER_LINE_2).map(|chunk| chunk.concat()).collect())
+    }
+
+    /// Decodes lines until some output is available or the input ends.
+    fn decode_more(&mut self) -> io::Result<()> {
+        while self.output.is_empty() && !self.done {
+            if !self.next_line()? {
+                self.done = true;
+                break;
+            }
+            if self.in_header {
+                self.decode_header_line()?;
+            } else if !self.line.is_empty() {
+                self.decode_epoch()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for HatanakaDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.output.len() {
+            self.output.clear();
+            self.position = 0;
+            self.decode_more()?;
+        }
+        let n = buf.len().min(self.output.len() - self.position);
+        buf[..n].copy_from_slice(&self.output[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header_line(content: &str, label: &str) -> String {
+        format!("{:<60}{}", content, label)
+    }
+
+    fn decode(lines: &[String]) -> Vec<String> {
+        let mut text = String::new();
+        HatanakaDecoder::new((lines.join("\n") + "\n").as_bytes()).read_to_string(&mut text).unwrap();
+        text.lines().map(|line| line.to_string()).collect()
+    }
+
+    /// Epoch line difference with `changes` at the given columns, `&` for a space.
+    fn epoch_diff(changes: &[(usize, &str)]) -> String {
+        let mut line = vec![b' '; 47];
+        for &(column, text) in changes {
+            line[column..column + text.len()].copy_from_slice(text.as_bytes());
+        }
+        String::from_utf8(line).unwrap().trim_end().to_string()
+    }
+
+    #[test]
+    fn crx3_round_trip() {
+        let header = vec![
+            header_line("3.0                 COMPACT RINEX FORMAT", CRINEX_LABEL),
+            header_line("RNX2CRX ver.4.1.0                       01-Jan-24 00:00", "CRINEX PROG / DATE"),
+            header_line("     3.04           OBSERVATION DATA    M", "RINEX VERSION / TYPE"),
+            header_line("G    3 C1C S1C S2W", "SYS / # / OBS TYPES"),
+            header_line("E    2 C1C S1C", "SYS / # / OBS TYPES"),
+            header_line("", "END OF HEADER"),
+        ];
+        let mut lines = header.clone();
+        lines.extend([
+            // Epoch 1: full epoch line, G05 and E11 start third-order arcs.
+            "> 2024 01 01 00 00 30.0000000  0  2      G05E11".to_string(),
+            String::new(),
+            "3&20123456789 3&45250 3&38500    8 7".to_string(),
+            " 3&41000   17".to_string(),
+            // Epoch 2: one second later, E11 gone; G05 first differences.
+            epoch_diff(&[(20, "1"), (34, "1"), (44, "&&&")]),
+            String::new(),
+            "1000 -250 500".to_string(),
+            // Epoch 3: E11 back, its flags differenced against a blank line.
+            epoch_diff(&[(20, "2"), (34, "2"), (44, "E11")]),
+            String::new(),
+            "0 0 0      6".to_string(),
+            " 3&41500    7".to_string(),
+        ]);
+
+        let mut expected: Vec<String> = header[2..].to_vec();
+        expected.extend([
+            "> 2024 01 01 00 00 30.0000000  0  2".to_string(),
+            "G05  20123456.789          45.250 8        38.500 7".to_string(),
+            format!("E11{:16}        41.00017", ""),
+            "> 2024 01 01 00 00 31.0000000  0  1".to_string(),
+            "G05  20123457.789          45.000 8        39.000 7".to_string(),
+            "> 2024 01 01 00 00 32.0000000  0  2".to_string(),
+            "G05  20123458.789          44.750 8        39.500 6".to_string(),
+            format!("E11{:16}        41.500 7", ""),
+        ]);
+        assert_eq!(decode(&lines), expected);
+    }
+
+    #[test]
+    fn missing_crinex_label_is_an_error() {
+        let text = header_line("     3.04           OBSERVATION DATA    M", "RINEX VERSION / TYPE") + "\n";
+        let mut out = String::new();
+        let error = HatanakaDecoder::new(text.as_bytes()).read_to_string(&mut out).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,233 @@
+use std::io::{self, Read};
+
+const MAGIC: [u8; 2] = [0x1f, 0x9d];
+const INIT_BITS: u32 = 9;
+const CLEAR: usize = 256;
+const BLOCK_MODE: u8 = 0x80;
+const BITS_MASK: u8 = 0x1f;
+
+/// Streaming decoder for Unix `compress` (`.Z`) files: LZW with 9 to 16 bit
+/// codes, as still used for many GNSS archives.
+pub struct LzwDecoder<R: Read> {
+    reader: R,
+    max_bits: u32,
+    block_mode: bool,
+    n_bits: u32,
+    max_code: usize,
+    /// Codes read at the current width. Codes come in groups of eight, and
+    /// `compress` skips the rest of a group whenever the width changes.
+    codes_in_group: usize,
+    bit_buffer: u64,
+    bit_count: u32,
+    prefix: Vec<u16>,
+    suffix: Vec<u8>,
+    free_entry: usize,
+    old_code: Option<usize>,
+    first_char: u8,
+    output: Vec<u8>,
+    position: usize,
+    stack: Vec<u8>,
+    started: bool,
+    done: bool,
+}
+
+impl<R: Read> LzwDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        LzwDecoder {
+            reader,
+            max_bits: 16,
+            block_mode: true,
+            n_bits: INIT_BITS,
+            max_code: (1 << INIT_BITS) - 1,
+            codes_in_group: 0,
+            bit_buffer: 0,
+            bit_count: 0,
+            prefix: vec![0; 1 << 16],
+            suffix: (0..1 << 16).map(|i| i as u8).collect(),
+            free_entry: 0,
+            old_code: None,
+            first_char: 0,
+            output: Vec::new(),
+            position: 0,
+            stack: Vec::new(),
+            started: false,
+            done: false,
+        }
+    }
+
+    fn read_header(&mut self) -> io::Result<()> {
+        let mut header = [0u8; 3];
+        self.reader.read_exact(&mut header)?;
+        if header[..2] != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a compress (.Z) stream"));
+        }
+        self.max_bits = (header[2] & BITS_MASK) as u32;
+        if !(INIT_BITS..=16).contains(&self.max_bits) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "unsupported compress code width"));
+        }
+        self.block_mode = header[2] & BLOCK_MODE != 0;
+        self.free_entry = if self.block_mode { CLEAR + 1 } else { CLEAR };
+        self.max_code = self.max_code_at(INIT_BITS);
+        self.started = true;
+        Ok(())
+    }
+
+    /// Largest code before the width grows; at full width the table just stops growing.
+    fn max_code_at(&self, n_bits: u32) -> usize {
+        if n_bits == self.max_bits { 1 << self.max_bits } else { (1 << n_bits) - 1 }
+    }
+
+    /// Next code of `n_bits` bits, least significant bits first. None at the
+    /// end of the input; a trailing partial code is padding.
+    fn read_code(&mut self) -> io::Result<Option<usize>> {
+        while self.bit_count < self.n_bits {
+            let mut byte = [0u8; 1];
+            match self.reader.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => {
+                    self.bit_buffer |= (byte[0] as u64) << self.bit_count;
+                    self.bit_count += 8;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        let code = (self.bit_buffer & ((1 << self.n_bits) - 1)) as usize;
+        self.bit_buffer >>= self.n_bits;
+        self.bit_count -= self.n_bits;
+        self.codes_in_group += 1;
+        Ok(Some(code))
+    }
+
+    /// Skips the codes left in the current group of eight.
+    fn skip_to_group_end(&mut self) -> io::Result<()> {
+        let remaining = (8 - self.codes_in_group % 8) % 8;
+        for _ in 0..remaining {
+            if self.read_code()?.is_none() {
+                break;
+            }
+        }
+        self.codes_in_group = 0;
+        Ok(())
+    }
+
+    /// Decodes codes until some output is available or the input ends.
+    fn decode_more(&mut self) -> io::Result<()> {
+        if !self.started {
+            self.read_header()?;
+        }
+        let max_max_code = 1usize << self.max_bits;
+
+        while self.output.is_empty() && !self.done {
+            if self.free_entry > self.max_code {
+                self.skip_to_group_end()?;
+                self.n_bits += 1;
+                self.max_code = self.max_code_at(self.n_bits);
+            }
+
+            let code = match self.read_code()? {
+                Some(code) => code,
+                None => {
+                    self.done = true;
+                    break;
+                }
+            };
+
+            let old_code = match self.old_code {
+                Some(old) => old,
+                None => {
+                    if code > 255 {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "corrupt compress stream"));
+                    }
+                    self.first_char = code as u8;
+                    self.old_code = Some(code);
+                    self.output.push(code as u8);
+                    continue;
+                }
+            };
+
+            if code == CLEAR && self.block_mode {
+                self.skip_to_group_end()?;
+                self.free_entry = CLEAR;
+                self.n_bits = INIT_BITS;
+                self.max_code = self.max_code_at(INIT_BITS);
+                continue;
+            }
+
+            let in_code = code;
+            let mut code = code;
+            self.stack.clear();
+            if code >= self.free_entry {
+                // The KwKwK case: the code being defined right now.
+                if code > self.free_entry {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "corrupt compress stream"));
+                }
+                self.stack.push(self.first_char);
+                code = old_code;
+            }
+            while code > 255 {
+                self.stack.push(self.suffix[code]);
+                code = self.prefix[code] as usize;
+            }
+            self.first_char = self.suffix[code];
+            self.stack.push(self.first_char);
+            self.output.extend(self.stack.iter().rev());
+
+            if self.free_entry < max_max_code {
+                self.prefix[self.free_entry] = old_code as u16;
+                self.suffix[self.free_entry] = self.first_char;
+                self.free_entry += 1;
+            }
+            self.old_code = Some(in_code);
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for LzwDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.output.len() {
+            self.output.clear();
+            self.position = 0;
+            self.decode_more()?;
+        }
+        let n = buf.len().min(self.output.len() - self.position);
+        buf[..n].copy_from_slice(&self.output[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        LzwDecoder::new(data).read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn short_stream() {
+        // `compress` output for the textbook example, including the KwKwK case.
+        let data = [
+            0x1f, 0x9d, 0x90, 0x54, 0x9e, 0x08, 0x29, 0xf2, 0x44, 0x8a, 0x93, 0x27,
+            0x54, 0x02, 0x0e, 0x2c, 0xa8, 0x90, 0xa0, 0x41, 0x84,
+        ];
+        assert_eq!(decompress(&data).unwrap(), b"TOBEORNOTTOBEORTOBEORNOT");
+    }
+
+    #[test]
+    fn stream_growing_past_nine_bit_codes() {
+        let expected: String = (1..100).rev().map(|i| format!("{} bottles of beer on the wall\n", i)).collect();
+        let data = include_bytes!("../../data/test/bottles.Z");
+        assert_eq!(decompress(data).unwrap(), expected.as_bytes());
+    }
+
+    #[test]
+    fn wrong_magic_is_an_error() {
+        let error = decompress(&[0x1f, 0x8b, 0x08, 0x00]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
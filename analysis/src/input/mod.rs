@@ -0,0 +1,63 @@
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, Read};
+use std::path::Path;
+
+use flate2::read::MultiGzDecoder;
+
+pub mod hatanaka;
+pub mod lzw;
+
+use hatanaka::{HatanakaDecoder, CRINEX_LABEL};
+use lzw::LzwDecoder;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const COMPRESS_MAGIC: [u8; 2] = [0x1f, 0x9d];
+
+/// Bytes looked at to recognise the format: the first line of a RINEX file.
+const SNIFF_LENGTH: u64 = 82;
+
+/// File name suffixes of the compression layers handled by [`decode`].
+const COMPRESSION_SUFFIXES: [&str; 3] = [".gz", ".Z", ".z"];
+
+/// Opens an input file, undoing its compression on the fly.
+pub fn open(path: impl AsRef<Path>) -> io::Result<Box<dyn Read>> {
+    decode(File::open(path)?)
+}
+
+/// Wraps a reader so that it yields plain text (or plain binary data)
+/// whatever layers of gzip, Unix compress and Hatanaka compact RINEX it is
+/// packed in, e.g. a `.crx.gz` or `.d.Z` file. Layers are recognised from
+/// the data rather than the file name, and nothing is staged on disk.
+pub fn decode<R: Read + 'static>(reader: R) -> io::Result<Box<dyn Read>> {
+    decode_boxed(Box::new(reader))
+}
+
+fn decode_boxed(reader: Box<dyn Read>) -> io::Result<Box<dyn Read>> {
+    let mut reader = BufReader::new(reader);
+    let mut head = Vec::new();
+    reader.by_ref().take(SNIFF_LENGTH).read_to_end(&mut head)?;
+
+    let is_crinex = head.get(60..)
+        .is_some_and(|label| label.starts_with(CRINEX_LABEL.as_bytes()));
+    let reader = Cursor::new(head.clone()).chain(reader);
+
+    if head.starts_with(&GZIP_MAGIC) {
+        decode_boxed(Box::new(MultiGzDecoder::new(reader)))
+    } else if head.starts_with(&COMPRESS_MAGIC) {
+        decode_boxed(Box::new(LzwDecoder::new(BufReader::new(reader))))
+    } else if is_crinex {
+        Ok(Box::new(HatanakaDecoder::new(BufReader::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// File name without its compression suffixes, e.g. `abcd0010.24d` for
+/// `abcd0010.24d.Z`, to tell the format of the content.
+pub fn content_name(file_path: &str) -> &str {
+    let mut name = file_path;
+    while let Some(stripped) = COMPRESSION_SUFFIXES.iter().find_map(|suffix| name.strip_suffix(suffix)) {
+        name = stripped;
+    }
+    name
+}
@@ -0,0 +1,17 @@
+pub mod db;
+pub mod nmea;
+pub mod orbit;
+pub mod config;
+pub mod gnssir;
+pub mod input;
+pub mod math;
+pub mod refraction;
+pub mod rinex;
+pub mod rtcm;
+pub mod snr;
+pub mod source;
+pub mod sp3;
+pub mod station;
+pub mod stream;
+pub mod time;
+pub mod ubx;
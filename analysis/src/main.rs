@@ -1,52 +1,104 @@
-use std::{time::Duration};
 use std::collections::VecDeque;
 
 use csv::Writer;
 
 
 
-mod db;
-mod nmea;
-mod config;
-mod gnssir;
-mod math;
+use realtime_gnssrefl::{config, db, gnssir, input, orbit, refraction, rinex, snr, source, sp3};
+use realtime_gnssrefl::station::StationMetadata;
+use realtime_gnssrefl::time::Epoch;
 
-fn read_nmea_file(file_path: &str) -> Vec<String> {
+fn read_source(config: &config::Config) -> (Vec<db::record::Record>, StationMetadata) {
     let start = std::time::Instant::now();
-    let lines = std::fs::read_to_string(file_path)
-        .expect("Failed to read NMEA file")
-        .lines()
-        .map(|line| line.to_string())
-        .collect::<Vec<String>>();
-    println!("Reading NMEA file took: {:?}", start.elapsed());
-    lines
+    let mut source = source::open(config).expect("Failed to open input");
+    let mut records = Vec::new();
+    source.run(&mut |batch, _| records.extend(batch)).expect("Failed to read input");
+    println!("Reading and parsing input took: {:?}", start.elapsed());
+    println!("Parse report: {}", source.summary());
+
+    let station = source.station();
+    match &station.position {
+        Some(position) => println!("Station position: {}", position),
+        None => println!("Station position: unknown"),
+    }
+    if let Some(height) = station.antenna_height {
+        println!("Antenna height: {:.4} m", height);
+    }
+    (records, station)
+}
+
+fn write_snr_file(file_path: &str, records: &VecDeque<db::record::Record>) {
+    let snr_type = file_path.rsplit_once('.')
+        .and_then(|(_, ext)| snr::SnrType::from_extension(ext))
+        .expect("SNR output must end in .snr50, .snr66, .snr88 or .snr99");
+    let file = std::fs::File::create(file_path).expect("Failed to create SNR file");
+    let report = snr::write_records(file, records, snr_type).expect("Failed to write SNR file");
+    println!("Wrote SNR file {}: {}", file_path, report);
 }
 
-fn parse_nmea(nmea_sentences: Vec<String>, config: &config::Config) -> Vec<db::record::Record> {
+fn read_nav_file(file_path: &str) -> orbit::BroadcastOrbits {
     let start = std::time::Instant::now();
-    let records = nmea::nmea_to_records(nmea_sentences, config);
-    println!("NMEA parsing took: {:?}", start.elapsed());
-    records
+    let file = input::open(file_path).expect("Failed to open RINEX navigation file");
+    let log = rinex::nav::read_navigation(file).expect("Failed to read RINEX navigation file");
+    println!("Reading and parsing RINEX {:.2} navigation file took: {:?}", log.version, start.elapsed());
+    println!("RINEX navigation report: {} for {} satellites", log.report, log.orbits.satellites());
+    log.orbits
 }
 
-fn find_arcs(records: &VecDeque<db::record::Record>) -> Vec<db::arc::Arc> {
+fn read_sp3_files(file_paths: &[String]) -> orbit::PreciseOrbits {
     let start = std::time::Instant::now();
-    let arcs = gnssir::find_arcs(records);
-    println!("Finding arcs took: {:?}", start.elapsed());
-    arcs
+    let mut orbits = orbit::PreciseOrbits::default();
+    for file_path in file_paths {
+        let file = input::open(file_path).expect("Failed to open SP3 file");
+        let log = sp3::read_sp3(file).expect("Failed to read SP3 file");
+        println!("SP3-{} report for {}: {}", log.version, file_path, log.report);
+        orbits.merge(log.orbits);
+    }
+    println!("Reading and parsing SP3 files took: {:?}", start.elapsed());
+    orbits
 }
 
-fn process_arcs(arcs: &Vec<db::arc::Arc>, records: &mut VecDeque<db::record::Record>) {
+/// Computes elevation and azimuth from the configured orbits, where the
+/// station position is known.
+fn apply_orbits(records: &mut Vec<db::record::Record>, station: &StationMetadata, config: &config::Config) {
+    let orbits: Box<dyn orbit::GeometrySource> = match &config.orbits {
+        orbit::OrbitSource::Receiver => return,
+        orbit::OrbitSource::Broadcast(path) => Box::new(read_nav_file(path)),
+        orbit::OrbitSource::Precise(paths) => Box::new(read_sp3_files(paths)),
+    };
+    let Some(position) = &station.position else {
+        println!("Station position unknown, keeping the receiver's elevation and azimuth");
+        return;
+    };
     let start = std::time::Instant::now();
-    for arc in arcs {
-        gnssir::fix_arc_elev_azim(arc, records);
+    let report = orbit::apply_geometry(records, orbits.as_ref(), position, config);
+    println!("Computing satellite geometry took: {:?}", start.elapsed());
+    println!("Geometry report: {}", report);
+}
+
+fn find_arcs(records: &VecDeque<db::record::Record>, config: &config::Config) -> Vec<db::arc::Arc> {
+    let start = std::time::Instant::now();
+    let log = gnssir::find_arcs(records, config);
+    println!("Finding arcs took: {:?}", start.elapsed());
+    for arc in &log.arcs {
+        let sector = arc.azimuth_sector.map_or(String::new(), |s| format!(" in {}", s));
+        println!("Arc for ID {}: {}{}, {} records from {} to {}", arc.sat_id, arc.direction, sector, arc.samples.len(), arc.time_start, arc.time_end);
+    }
+    for rejected in &log.rejected {
+        let arc = &rejected.arc;
+        let sector = arc.azimuth_sector.map_or(String::new(), |s| format!(" in {}", s));
+        println!("Rejected arc for ID {}: {}{}, {} records from {} to {}: {}", arc.sat_id, arc.direction, sector, arc.samples.len(), arc.time_start, arc.time_end, rejected.reason);
     }
-    println!("Fixing arc elevation and azimuth took: {:?}", start.elapsed());
+    log.arcs
+}
+
+fn process_arcs(arcs: &mut [db::arc::Arc], station: &StationMetadata, config: &config::Config) {
     let start = std::time::Instant::now();
-    for arc in arcs {
-        gnssir::correct_arc_snr(arc, records);
+    let weather = refraction::Weather::at_station(station);
+    for arc in arcs.iter_mut() {
+        gnssir::prepare_arc(arc, &weather, config);
     }
-    println!("Correcting arc SNR took: {:?}", start.elapsed());
+    println!("Preparing arcs (refraction {:?} at {:.1} hPa, {:.1} °C) took: {:?}", config.refraction, weather.pressure, weather.temperature, start.elapsed());
 }
 
 fn start_csv(file_path: &str, headers: &[&str]) -> Writer<std::fs::File> {
@@ -63,21 +115,20 @@ fn flush_csv(wtr: &mut Writer<std::fs::File>) {
     wtr.flush().expect("Failed to flush CSV writer");
 }
 
-fn find_results(arcs: &Vec<db::arc::Arc>, records: &VecDeque<db::record::Record>, config: &config::Config) {
+fn find_results(arcs: &[db::arc::Arc], config: &config::Config) {
     let mut wtr = start_csv("results/arc_freqs.csv", &["i", "id", "frequency", "amplitude", "num"]);
 
     let mut freqs: Vec<Vec<(f64, f64)>> = Vec::new();
 
     let start = std::time::Instant::now();
-    for id in 0..arcs.len() {
-        let arc = &arcs[id];
+    for (id, arc) in arcs.iter().enumerate() {
         let start2 = std::time::Instant::now();
-        let frequencies = gnssir::find_arc_frequencies(arc, records, &config);
+        let frequencies = gnssir::find_arc_frequencies(arc, config);
         let duration2 = start2.elapsed();
         println!("Arc ID {}: Found {} frequency components in {:?}", arc.sat_id, frequencies.len(), duration2);
 
         for (freq, amp) in &frequencies {
-            write_to_csv(&mut wtr, &[id.to_string(), arc.sat_id.to_string(), freq.to_string(), amp.to_string(), arc.record_indices.len().to_string()]);
+            write_to_csv(&mut wtr, &[id.to_string(), arc.sat_id.to_string(), freq.to_string(), amp.to_string(), arc.samples.len().to_string()]);
         }
         freqs.push(frequencies);
     }
@@ -89,23 +140,23 @@ fn find_results(arcs: &Vec<db::arc::Arc>, records: &VecDeque<db::record::Record>
     
     for (arc, frequencies) in arcs.iter().zip(freqs.iter()) {
         if let Some((freq, amp)) = gnssir::find_max_amplitude_frequency(frequencies) {
-            let mean_elev = arc.record_indices.iter()
-                .filter_map(|&idx| records.get(idx).map(|rec| rec.elevation))
-                .sum::<f64>() / arc.record_indices.len() as f64;
-            let mean_azim = arc.record_indices.iter()
-                .filter_map(|&idx| records.get(idx).map(|rec| rec.azimuth))
-                .sum::<f64>() / arc.record_indices.len() as f64;
+            let mean_elev = arc.samples.iter()
+                .map(|sample| sample.elevation)
+                .sum::<f64>() / arc.samples.len() as f64;
+            let mean_azim = arc.samples.iter()
+                .map(|sample| sample.azimuth)
+                .sum::<f64>() / arc.samples.len() as f64;
             let mean_ampl = frequencies.iter().map(|(_,a)| *a).sum::<f64>() / frequencies.len() as f64;
             let median_time = {
-                let mut times: Vec<i64> = arc.record_indices.iter()
-                    .filter_map(|&idx| records.get(idx).map(|rec| rec.time))
+                let mut times: Vec<Epoch> = arc.samples.iter()
+                    .map(|sample| sample.time)
                     .collect();
                 times.sort();
                 times[times.len() / 2]
             };
 
             println!("Arc ID {}: Max amplitude frequency {:.4} with amplitude {:.4} (mean: {:.4}) at mean elev {:.2}, azim {:.2}, median time {}, num records {}",
-                arc.sat_id, freq, amp, mean_ampl, mean_elev, mean_azim, median_time, arc.record_indices.len());
+                arc.sat_id, freq, amp, mean_ampl, mean_elev, mean_azim, median_time, arc.samples.len());
         }
     }
     println!("Collecting results took: {:?}", start.elapsed());
@@ -113,13 +164,48 @@ fn find_results(arcs: &Vec<db::arc::Arc>, records: &VecDeque<db::record::Record>
 
 fn main() {
     let start: std::time::Instant = std::time::Instant::now();
-    let config: config::Config = config::Config::default();
+    let mut config: config::Config = config::Config::default();
     let mut record_db: db::record::RecordDatabase = db::record::RecordDatabase::new();
 
-    let nmea_sentences = read_nmea_file("data/nmea2.txt");
-    let records = parse_nmea(nmea_sentences, &config);
+    // Usage: realtime-gnssrefl [INPUT] [--format FORMAT] [--nav NAV_FILE | --sp3 SP3_FILE...] [--snr SNR_OUTPUT]
+    //                          [--refraction none|bennett|meteorological] [--weather PRESSURE,TEMPERATURE]
+    // INPUT is a file, `-` for stdin, `tcp://host:port` or a serial device under /dev.
+    let mut snr_output = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--nav" => config.orbits = orbit::OrbitSource::Broadcast(args.next().expect("--nav needs a RINEX navigation file")),
+            "--sp3" => {
+                let file = args.next().expect("--sp3 needs an SP3 file");
+                match &mut config.orbits {
+                    orbit::OrbitSource::Precise(files) => files.push(file),
+                    orbits => *orbits = orbit::OrbitSource::Precise(vec![file]),
+                }
+            }
+            "--snr" => snr_output = Some(args.next().expect("--snr needs an output file")),
+            "--refraction" => {
+                let name = args.next().expect("--refraction needs none, bennett or meteorological");
+                config.refraction = refraction::RefractionModel::from_name(&name).expect("Unknown refraction model");
+            }
+            "--weather" => {
+                let weather = args.next().expect("--weather needs PRESSURE,TEMPERATURE in hPa and degrees Celsius");
+                config.weather = Some(refraction::Weather::from_arg(&weather).expect("Malformed --weather"));
+            }
+            "--format" => {
+                let name = args.next().expect("--format needs nmea, ubx, rtcm, rinex or snr");
+                config.input_format = Some(source::InputFormat::from_name(&name).expect("Unknown input format"));
+            }
+            _ => config.source = source::SourceKind::from_arg(&arg),
+        }
+    }
+
+    let (mut records, mut station) = read_source(&config);
+    if config.weather.is_some() {
+        station.weather = config.weather;
+    }
+    apply_orbits(&mut records, &station, &config);
 
-    println!("Parsed {} records from NMEA sentences.", records.len());
+    println!("Parsed {} records from {:?}.", records.len(), config.source);
 
     record_db.insert_many(records);
 
@@ -138,10 +224,10 @@ fn main() {
 
     println!("Database now contains {} records, with size {} KB", record_db.len(), record_db.check_memory()/(1024));
     
-    // let arcs = find_arcs(&record_db.records);
-    // println!("Found {} arcs in the records.", arcs.len());
-    
-    // process_arcs(&arcs, &mut record_db.records);
+    let mut arcs = find_arcs(&record_db.records, &config);
+    println!("Found {} arcs in the records.", arcs.len());
+
+    process_arcs(&mut arcs, &station, &config);
 
     let mut wtr = start_csv("results/records.csv", &["id", "time", "network", "band", "elevation", "azimuth", "snr"]);
     for record in &record_db.records {
@@ -149,7 +235,7 @@ fn main() {
             &mut wtr,
             &[
                 record.id.to_string(),
-                record.time.to_string(),
+                record.time.gps_seconds().to_string(),
                 format!("{:?}", record.network),
                 format!("{:?}", record.band),
                 record.elevation.to_string(),
@@ -160,6 +246,10 @@ fn main() {
     }
     flush_csv(&mut wtr);
 
-    // find_results(&arcs, &record_db.records, &config);
-    // println!("Total runtime: {:?}", start.elapsed());
+    if let Some(snr_output) = snr_output {
+        write_snr_file(&snr_output, &record_db.records);
+    }
+
+    find_results(&arcs, &config);
+    println!("Total runtime: {:?}", start.elapsed());
 }
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+
+use crate::db::record::Network;
+use crate::time::Epoch;
+use super::SentenceError;
+
+const SATELLITES_PER_SENTENCE: usize = 4;
+
+#[derive(Debug, Clone, Copy)]
+pub struct GsvSatellite {
+    pub id: u32,
+    pub elevation: Option<f64>,
+    pub azimuth: Option<f64>,
+    pub snr: Option<f64>,
+}
+
+/// One parsed `xxGSV` sentence, i.e. a single part of a group.
+#[derive(Debug, Clone)]
+pub struct GsvSentence {
+    pub talker: [u8; 2],
+    pub network: Network,
+    pub num_messages: usize,
+    pub idx_of_message: usize,
+    pub num_satellites: usize,
+    pub signal_id: Option<u32>,
+    pub satellites: Vec<GsvSatellite>,
+}
+
+impl GsvSentence {
+    /// Checks the message counters and the number of satellites against each other.
+    fn validate(&self) -> Result<(), SentenceError> {
+        if self.num_messages == 0 || self.idx_of_message == 0 || self.idx_of_message > self.num_messages {
+            return Err(SentenceError::InconsistentGroup);
+        }
+
+        let max_satellites = self.num_messages * SATELLITES_PER_SENTENCE;
+        let min_satellites = (self.num_messages - 1) * SATELLITES_PER_SENTENCE + 1;
+        let empty_group = self.num_satellites == 0 && self.num_messages == 1;
+        if !empty_group && (self.num_satellites < min_satellites || self.num_satellites > max_satellites) {
+            return Err(SentenceError::InconsistentGroup);
+        }
+
+        let before = (self.idx_of_message - 1) * SATELLITES_PER_SENTENCE;
+        let expected = self.num_satellites.saturating_sub(before).min(SATELLITES_PER_SENTENCE);
+        if self.satellites.len() != expected {
+            return Err(SentenceError::InconsistentGroup);
+        }
+
+        Ok(())
+    }
+}
+
+/// All satellites of one talker/signal group at one epoch.
+#[derive(Debug, Clone)]
+pub struct GsvGroup {
+    pub network: Network,
+    pub signal_id: Option<u32>,
+    pub time: Epoch,
+    pub satellites: Vec<GsvSatellite>,
+    /// False when one or more sentences of the group never arrived.
+    pub complete: bool,
+}
+
+struct PendingGroup {
+    num_messages: usize,
+    num_satellites: usize,
+    last_idx: usize,
+    received: usize,
+    group: GsvGroup,
+}
+
+impl PendingGroup {
+    fn finish(mut self) -> GsvGroup {
+        self.group.complete = self.received == self.num_messages;
+        self.group
+    }
+}
+
+/// Collects the parts of multi-sentence GSV groups and only hands out a group
+/// once its last sentence has been seen, or when it is abandoned.
+#[derive(Default)]
+pub struct GsvAssembler {
+    pending: HashMap<([u8; 2], Option<u32>), PendingGroup>,
+}
+
+impl GsvAssembler {
+    pub fn new() -> Self {
+        GsvAssembler::default()
+    }
+
+    /// Adds a sentence to its group. Returns the groups that were finished by
+    /// this sentence: the group itself once its last part arrives, and any
+    /// earlier group of the same talker/signal that was abandoned half-way.
+    pub fn push(&mut self, sentence: GsvSentence, time: Epoch) -> Result<Vec<GsvGroup>, SentenceError> {
+        sentence.validate()?;
+
+        let mut finished = Vec::new();
+        let key = (sentence.talker, sentence.signal_id);
+
+        let restart = match self.pending.get(&key) {
+            Some(p) => {
+                p.group.time != time
+                    || p.num_messages != sentence.num_messages
+                    || p.num_satellites != sentence.num_satellites
+                    || sentence.idx_of_message <= p.last_idx
+            }
+            None => false,
+        };
+        if restart && let Some(p) = self.pending.remove(&key) {
+            finished.push(p.finish());
+        }
+
+        let pending = self.pending.entry(key).or_insert_with(|| PendingGroup {
+            num_messages: sentence.num_messages,
+            num_satellites: sentence.num_satellites,
+            last_idx: 0,
+            received: 0,
+            group: GsvGroup {
+                network: sentence.network,
+                signal_id: sentence.signal_id,
+                time,
+                satellites: Vec::with_capacity(sentence.num_satellites),
+                complete: false,
+            },
+        });
+
+        pending.last_idx = sentence.idx_of_message;
+        pending.received += 1;
+        pending.group.satellites.extend(sentence.satellites);
+
+        if sentence.idx_of_message == sentence.num_messages && let Some(p) = self.pending.remove(&key) {
+            finished.push(p.finish());
+        }
+
+        Ok(finished)
+    }
+
+    /// Closes every open group, e.g. at the end of the input. Groups that are
+    /// still open at this point are by definition partial.
+    pub fn flush(&mut self) -> Vec<GsvGroup> {
+        self.pending.drain().map(|(_, p)| p.finish()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn satellite(id: u32) -> GsvSatellite {
+        GsvSatellite { id, elevation: Some(5.0), azimuth: Some(90.0), snr: Some(40.0) }
+    }
+
+    fn part(idx_of_message: usize, ids: &[u32]) -> GsvSentence {
+        GsvSentence {
+            talker: *b"GP",
+            network: Network::GPS,
+            num_messages: 2,
+            idx_of_message,
+            num_satellites: 5,
+            signal_id: Some(1),
+            satellites: ids.iter().map(|&id| satellite(id)).collect(),
+        }
+    }
+
+    #[test]
+    fn group_comes_out_once_complete() {
+        let time = Epoch::from_unix_utc_millis(1_700_000_000_000);
+        let mut assembler = GsvAssembler::new();
+        assert!(assembler.push(part(1, &[1, 2, 3, 4]), time).unwrap().is_empty());
+        let groups = assembler.push(part(2, &[5]), time).unwrap();
+        assert_eq!(groups.len(), 1);
+        assert!(groups[0].complete);
+        assert_eq!(groups[0].satellites.iter().map(|s| s.id).collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn abandoned_group_is_partial() {
+        let time = Epoch::from_unix_utc_millis(1_700_000_000_000);
+        let mut assembler = GsvAssembler::new();
+        assembler.push(part(1, &[1, 2, 3, 4]), time).unwrap();
+        // The next epoch starts before part 2 arrived.
+        let groups = assembler.push(part(1, &[1, 2, 3, 4]), Epoch::from_unix_utc_millis(1_700_000_001_000)).unwrap();
+        assert_eq!(groups.len(), 1);
+        assert!(!groups[0].complete);
+        assert_eq!(groups[0].time, time);
+    }
+
+    #[test]
+    fn inconsistent_counts_are_rejected() {
+        let time = Epoch::from_unix_utc_millis(1_700_000_000_000);
+        let mut assembler = GsvAssembler::new();
+        // Part 2 of a five-satellite group holds exactly one satellite.
+        assert_eq!(assembler.push(part(2, &[5, 6]), time).unwrap_err(), SentenceError::InconsistentGroup);
+        assert_eq!(assembler.push(part(3, &[5]), time).unwrap_err(), SentenceError::InconsistentGroup);
+    }
+}
@@ -0,0 +1,767 @@
+use std::fmt;
+use std::io::{self, Read};
+
+use crate::db::record::{self, Band, Network, Quality, Record};
+use crate::config::Config;
+use crate::source::StreamSource;
+use crate::stream::RecordParser;
+use crate::station::{FixQuality, PositionAverager, PositionFix, StationMetadata};
+use crate::time::{days_from_civil, Epoch, MILLIS_PER_DAY};
+
+pub mod gsv;
+pub mod quality;
+
+use gsv::{GsvAssembler, GsvGroup, GsvSatellite, GsvSentence};
+use quality::EpochQuality;
+
+/// Reasons a sentence can be rejected before or while it is parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SentenceError {
+    NotNmea,
+    Truncated,
+    BadChecksum,
+    UnknownTalker,
+    UnparsableField,
+    InconsistentGroup,
+}
+
+/// Per-category counts of how the sentences of a log were handled.
+#[derive(Debug, Clone, Default)]
+pub struct ParseReport {
+    pub sentences: usize,
+    pub accepted: usize,
+    pub ignored: usize,
+    pub not_nmea: usize,
+    pub truncated: usize,
+    pub bad_checksum: usize,
+    pub unknown_talker: usize,
+    pub unparsable_field: usize,
+    pub inconsistent_group: usize,
+    pub partial_groups: usize,
+    pub undated: usize,
+    pub unknown_satellites: usize,
+    pub excluded_satellites: usize,
+    /// Observations dropped because another signal on the same band came first.
+    pub duplicate_signals: usize,
+}
+
+impl ParseReport {
+    pub fn reject(&mut self, error: SentenceError) {
+        match error {
+            SentenceError::NotNmea => self.not_nmea += 1,
+            SentenceError::Truncated => self.truncated += 1,
+            SentenceError::BadChecksum => self.bad_checksum += 1,
+            SentenceError::UnknownTalker => self.unknown_talker += 1,
+            SentenceError::UnparsableField => self.unparsable_field += 1,
+            SentenceError::InconsistentGroup => self.inconsistent_group += 1,
+        }
+    }
+
+    pub fn rejected(&self) -> usize {
+        self.not_nmea + self.truncated + self.bad_checksum + self.unknown_talker + self.unparsable_field + self.inconsistent_group
+    }
+}
+
+impl fmt::Display for ParseReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} sentences: {} accepted, {} ignored, {} rejected (not NMEA: {}, truncated: {}, bad checksum: {}, unknown talker: {}, unparsable field: {}, inconsistent group: {}), {} partial GSV groups, {} GSV sentences before the first date, {} unknown and {} excluded satellite observations, {} duplicate signals",
+            self.sentences,
+            self.accepted,
+            self.ignored,
+            self.rejected(),
+            self.not_nmea,
+            self.truncated,
+            self.bad_checksum,
+            self.unknown_talker,
+            self.unparsable_field,
+            self.inconsistent_group,
+            self.partial_groups,
+            self.undated,
+            self.unknown_satellites,
+            self.excluded_satellites,
+            self.duplicate_signals,
+        )
+    }
+}
+
+/// Longest line kept while waiting for its newline; NMEA allows 82 characters,
+/// so anything longer is line noise. Such a line is dropped as a whole and
+/// counted as not NMEA.
+const MAX_LINE_LENGTH: usize = 1024;
+
+/// Incremental NMEA parser. Bytes can be fed in arbitrary chunks (e.g. straight
+/// from a UART read); records come out once their epoch is over, as the
+/// quality sentences (GSA/GST/GBS) of an epoch may follow its GSV groups.
+pub struct NmeaParser {
+    config: Config,
+    buffer: Vec<u8>,
+    /// Whether the line in `buffer` has run past `MAX_LINE_LENGTH`.
+    overlong: bool,
+    clock: NmeaClock,
+    current_time: Option<Epoch>,
+    assembler: GsvAssembler,
+    positions: PositionAverager,
+    epoch_quality: EpochQuality,
+    epoch_records: Vec<Record>,
+    report: ParseReport,
+}
+
+impl NmeaParser {
+    pub fn new(config: &Config) -> Self {
+        NmeaParser {
+            config: config.clone(),
+            buffer: Vec::with_capacity(MAX_LINE_LENGTH),
+            overlong: false,
+            clock: NmeaClock::default(),
+            current_time: None,
+            assembler: GsvAssembler::new(),
+            positions: PositionAverager::new(config.max_position_hdop),
+            epoch_quality: EpochQuality::default(),
+            epoch_records: Vec::new(),
+            report: ParseReport::default(),
+        }
+    }
+
+    pub fn report(&self) -> &ParseReport {
+        &self.report
+    }
+
+    /// Station metadata derived from the GGA positions seen so far.
+    pub fn station_metadata(&self) -> StationMetadata {
+        StationMetadata {
+            position: self.positions.average(),
+            ..StationMetadata::default()
+        }
+    }
+
+    /// Closes the open GSV groups, attaches the quality of the epoch to its
+    /// records and hands them out.
+    fn finish_epoch(&mut self, records: &mut Vec<Record>) {
+        for group in self.assembler.flush() {
+            group_to_records_into(group, &self.config, &mut self.report, &mut self.epoch_records);
+        }
+        // Receivers list the signals of a band in ascending signal id, so the primary one wins.
+        self.report.duplicate_signals += record::keep_first_signal(&mut self.epoch_records, 0);
+        for mut record in self.epoch_records.drain(..) {
+            self.epoch_quality.apply(&mut record);
+            records.push(record);
+        }
+        self.epoch_quality = EpochQuality::default();
+    }
+
+    fn push_line(&mut self, line: &[u8], records: &mut Vec<Record>) {
+        if std::mem::take(&mut self.overlong) {
+            self.report.sentences += 1;
+            self.report.reject(SentenceError::NotNmea);
+            return;
+        }
+        match std::str::from_utf8(line) {
+            Ok(sentence) => self.push_sentence(sentence, records),
+            Err(_) => {
+                self.report.sentences += 1;
+                self.report.reject(SentenceError::NotNmea);
+            }
+        }
+    }
+
+    /// Parses a single sentence, with or without line ending.
+    pub fn push_sentence(&mut self, sentence: &str, records: &mut Vec<Record>) {
+        // println!("Processing NMEA sentence: {}", sentence);
+        let cleaned = sentence.trim();
+        if cleaned.is_empty() {
+            return;
+        }
+        self.report.sentences += 1;
+
+        let payload = match verify_nmea_sentence(cleaned) {
+            Ok(payload) => payload,
+            Err(e) => {
+                self.report.reject(e);
+                return;
+            }
+        };
+
+        let header = payload.split(',').next().unwrap_or("");
+        let result = if ["GGA", "RMC", "ZDA"].iter().any(|c| is_nmea_command(header, c)) {
+            let epoch = find_epoch(header, payload, &mut self.clock).map(|epoch| {
+                if let Some(epoch) = epoch && self.current_time != Some(epoch) {
+                    // Groups still open belong to the previous epoch and will not be completed anymore.
+                    self.finish_epoch(records);
+                    self.current_time = Some(epoch);
+                }
+            });
+            // A broken position does not make the time of the GGA any less valid.
+            let position = if is_nmea_command(header, "GGA") {
+                find_gga_position(payload).map(|fix| {
+                    if let Some(fix) = fix {
+                        self.positions.push(fix);
+                    }
+                })
+            } else {
+                Ok(())
+            };
+            epoch.and(position)
+        } else if is_nmea_command(header, "GSV") {
+            let time = match self.current_time {
+                Some(t) => t,
+                None => {
+                    self.report.undated += 1;
+                    return;
+                }
+            };
+            parse_gsv_sentence(payload)
+                .and_then(|sentence| self.assembler.push(sentence, time))
+                .map(|groups| {
+                    for group in groups {
+                        group_to_records_into(group, &self.config, &mut self.report, &mut self.epoch_records);
+                    }
+                })
+        } else if is_nmea_command(header, "GSA") {
+            self.epoch_quality.push_gsa(payload)
+        } else if is_nmea_command(header, "GST") {
+            self.epoch_quality.push_gst(payload)
+        } else if is_nmea_command(header, "GBS") {
+            self.epoch_quality.push_gbs(payload)
+        } else {
+            self.report.ignored += 1;
+            return;
+        };
+
+        match result {
+            Ok(()) => self.report.accepted += 1,
+            Err(e) => self.report.reject(e),
+        }
+    }
+}
+
+impl RecordParser for NmeaParser {
+    /// Feeds a chunk of raw bytes. Lines split over chunks are kept until their
+    /// newline arrives.
+    fn push_bytes(&mut self, chunk: &[u8], records: &mut Vec<Record>) {
+        for &byte in chunk {
+            if byte == b'\n' {
+                let line = std::mem::take(&mut self.buffer);
+                self.push_line(&line, records);
+                self.buffer = line;
+                self.buffer.clear();
+            } else if self.buffer.len() < MAX_LINE_LENGTH {
+                self.buffer.push(byte);
+            } else {
+                self.overlong = true;
+            }
+        }
+    }
+
+    /// Parses whatever is left in the line buffer and closes the current
+    /// epoch, e.g. at the end of a file.
+    fn finish(&mut self, records: &mut Vec<Record>) {
+        let line = std::mem::take(&mut self.buffer);
+        self.push_line(&line, records);
+        self.finish_epoch(records);
+    }
+
+    fn station(&self) -> StationMetadata {
+        self.station_metadata()
+    }
+
+    fn summary(&self) -> String {
+        format!("NMEA: {}", self.report)
+    }
+}
+
+/// Everything extracted from a complete NMEA stream.
+pub struct NmeaLog {
+    pub records: Vec<Record>,
+    pub report: ParseReport,
+    pub station: StationMetadata,
+}
+
+/// Parses a complete NMEA stream, e.g. a log file.
+pub fn read_records<R: Read>(reader: R, config: &Config) -> io::Result<NmeaLog> {
+    let mut source = StreamSource::new(reader, NmeaParser::new(config));
+    let records = source.read_all()?;
+    Ok(NmeaLog {
+        records,
+        report: source.parser().report().clone(),
+        station: source.parser().station_metadata(),
+    })
+}
+
+/// Talker ids accepted from GNSS receivers and the constellation each one
+/// reports. `GN` (combined) stands for no constellation in particular; its
+/// satellites are told apart by id.
+const TALKERS: [(&str, Network); 8] = [
+    ("GP", Network::GPS),
+    ("GA", Network::Galileo),
+    ("GB", Network::BeiDou),
+    ("BD", Network::BeiDou),
+    ("GL", Network::GLONASS),
+    ("GQ", Network::QZSS),
+    ("GI", Network::NavIC),
+    ("GN", Network::Unknown),
+];
+
+/// The constellation of a sentence's talker, or `None` for talkers not in `TALKERS`.
+fn command_to_network(header: &str) -> Option<Network> {
+    let talker = header.get(0..2)?;
+    TALKERS.iter().find(|(t, _)| *t == talker).map(|&(_, network)| network)
+}
+
+fn is_known_talker(header: &str) -> bool {
+    command_to_network(header).is_some()
+}
+
+/// Maps the satellite id of a GSV block to the constellation and PRN/slot it
+/// belongs to. GP groups also carry SBAS and QZSS satellites in their
+/// extended id ranges. GN groups (`Network::Unknown`) are resolved only by
+/// ids that are unique across constellations; ids 1-32 there may be the PRN
+/// of any constellation and are dropped. Returns `None` for ids outside the
+/// ranges of the talker.
+fn normalise_satellite(satellite: u32, network: Network) -> Option<(Network, u32)> {
+    let normalised = match (network, satellite) {
+        (Network::GPS, 1..=32) => (Network::GPS, satellite),
+        (Network::GPS | Network::Unknown, 33..=64) => (Network::SBAS, satellite + 87),
+        (Network::GPS | Network::Unknown, 120..=158) => (Network::SBAS, satellite),
+        (Network::GPS | Network::Unknown | Network::QZSS, 193..=202) => (Network::QZSS, satellite - 192),
+        (Network::GLONASS | Network::Unknown, 65..=96) => (Network::GLONASS, satellite - 64),
+        // Extended numbering of combined (GN) output.
+        (Network::Unknown, 301..=336) => (Network::Galileo, satellite - 300),
+        (Network::Unknown, 401..=463) => (Network::BeiDou, satellite - 400),
+        (Network::Galileo, 1..=36) => (Network::Galileo, satellite),
+        (Network::BeiDou, 1..=63) => (Network::BeiDou, satellite),
+        (Network::QZSS, 1..=10) => (Network::QZSS, satellite),
+        (Network::NavIC, 1..=14) => (Network::NavIC, satellite),
+        _ => return None,
+    };
+    Some(normalised)
+}
+
+/// Maps an NMEA 4.11 signal id to the carrier it is transmitted on. The same
+/// id means a different signal for every constellation.
+fn signal_to_band(network: Network, signal_id: u32) -> Band {
+    match (network, signal_id) {
+        (Network::GPS, 1..=3) => Band::L1,
+        (Network::GPS, 4..=6) => Band::L2,
+        (Network::GPS, 7..=8) => Band::L5,
+        (Network::GLONASS, 1..=2) => Band::G1,
+        (Network::GLONASS, 3..=4) => Band::G2,
+        (Network::Galileo, 1) => Band::E5a,
+        (Network::Galileo, 2) => Band::E5b,
+        (Network::Galileo, 3) => Band::E5,
+        (Network::Galileo, 4..=5) => Band::E6,
+        (Network::Galileo, 6..=7) => Band::E1,
+        (Network::BeiDou, 1..=2) => Band::B1I,
+        (Network::BeiDou, 3..=4) => Band::B1C,
+        (Network::BeiDou, 5) => Band::B2a,
+        (Network::BeiDou, 6) => Band::B2b,
+        (Network::BeiDou, 7) => Band::B2,
+        (Network::BeiDou, 8..=0xA) => Band::B3,
+        (Network::BeiDou, 0xB..=0xC) => Band::B2b,
+        (Network::QZSS, 1..=4) => Band::L1,
+        (Network::QZSS, 5..=6) => Band::L2,
+        (Network::QZSS, 7..=8) => Band::L5,
+        (Network::QZSS, 9..=0xA) => Band::L6,
+        (Network::NavIC, 1 | 3) => Band::L5,
+        (Network::NavIC, 2 | 4) => Band::S,
+        (Network::NavIC, 5) => Band::L1,
+        // SBAS satellites are reported in GPS groups and use the GPS signal ids.
+        (Network::SBAS, 1..=3) => Band::L1,
+        (Network::SBAS, 7..=8) => Band::L5,
+        _ => Band::Unknown,
+    }
+}
+
+/// Checks the framing and `*hh` checksum of a sentence and returns the payload
+/// between `$` and `*`.
+fn verify_nmea_sentence(sentence: &str) -> Result<&str, SentenceError> {
+    let body = sentence.strip_prefix('$').ok_or(SentenceError::NotNmea)?;
+    let (payload, checksum) = body.rsplit_once('*').ok_or(SentenceError::Truncated)?;
+
+    if checksum.len() < 2 {
+        return Err(SentenceError::Truncated);
+    }
+    // Exactly two hex digits; a corrupted line may carry anything here.
+    let expected = checksum
+        .get(0..2)
+        .filter(|digits| digits.bytes().all(|b| b.is_ascii_hexdigit()))
+        .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+        .ok_or(SentenceError::BadChecksum)?;
+    let actual = payload.bytes().fold(0u8, |acc, b| acc ^ b);
+    if actual != expected {
+        return Err(SentenceError::BadChecksum);
+    }
+
+    // Proprietary sentences ($P...) have a manufacturer code instead of a talker.
+    if !payload.starts_with('P') && !is_known_talker(payload) {
+        return Err(SentenceError::UnknownTalker);
+    }
+
+    Ok(payload)
+}
+
+fn is_nmea_command(header: &str, command: &str) -> bool {
+    !header.starts_with('P') && header.get(2..5) == Some(command)
+}
+
+fn parse_field<T: std::str::FromStr>(field: Option<&str>) -> Result<T, SentenceError> {
+    field.ok_or(SentenceError::Truncated)?
+        .parse::<T>()
+        .map_err(|_| SentenceError::UnparsableField)
+}
+
+/// Parses an optional numeric field, where an empty field means "not available".
+fn parse_optional_field<T: std::str::FromStr>(field: Option<&str>) -> Result<Option<T>, SentenceError> {
+    match field {
+        None => Err(SentenceError::Truncated),
+        Some("") => Ok(None),
+        Some(v) => v.parse::<T>().map(Some).map_err(|_| SentenceError::UnparsableField),
+    }
+}
+
+/// Turns the time-of-day stamps of GGA/RMC/ZDA into absolute epochs, using the
+/// last date seen in RMC/ZDA and rolling it over at midnight when no new date
+/// has arrived yet.
+#[derive(Default)]
+struct NmeaClock {
+    date: Option<i64>,
+    last_millis_of_day: Option<i64>,
+}
+
+impl NmeaClock {
+    fn update(&mut self, date: Option<i64>, millis_of_day: i64) -> Option<Epoch> {
+        match date {
+            Some(d) => self.date = Some(d),
+            None => {
+                if let (Some(d), Some(last)) = (self.date, self.last_millis_of_day)
+                    && millis_of_day + MILLIS_PER_DAY / 2 < last
+                {
+                    self.date = Some(d + 1);
+                }
+            }
+        }
+        self.last_millis_of_day = Some(millis_of_day);
+        self.date.map(|d| Epoch::from_utc_days(d, millis_of_day))
+    }
+}
+
+/// Parses `hhmmss[.sss]` into milliseconds since midnight.
+fn parse_time_of_day(time_str: &str) -> Result<i64, SentenceError> {
+    if time_str.len() < 6 {
+        return Err(SentenceError::UnparsableField);
+    }
+
+    let hours = parse_field::<i64>(time_str.get(0..2))?;
+    let minutes = parse_field::<i64>(time_str.get(2..4))?;
+    let seconds = parse_field::<f64>(time_str.get(4..))?;
+
+    Ok((hours * 3600 + minutes * 60) * 1000 + (seconds * 1000.0).round() as i64)
+}
+
+/// Parses `ddmm.mmmm` / `dddmm.mmmm` with its hemisphere into signed degrees.
+fn parse_coordinate(value: &str, hemisphere: &str, degree_digits: usize) -> Result<f64, SentenceError> {
+    let degrees = parse_field::<f64>(value.get(..degree_digits))?;
+    let minutes = parse_field::<f64>(value.get(degree_digits..))?;
+    let coordinate = degrees + minutes / 60.0;
+    match hemisphere {
+        "N" | "E" => Ok(coordinate),
+        "S" | "W" => Ok(-coordinate),
+        _ => Err(SentenceError::UnparsableField),
+    }
+}
+
+/// Extracts the receiver position from GGA. Returns `None` when the receiver
+/// has no position yet.
+fn find_gga_position(payload: &str) -> Result<Option<PositionFix>, SentenceError> {
+    let fields: Vec<&str> = payload.split(',').collect();
+    if fields.len() < 12 {
+        return Err(SentenceError::Truncated);
+    }
+    if fields[2].is_empty() || fields[4].is_empty() || fields[9].is_empty() {
+        return Ok(None);
+    }
+
+    let quality = FixQuality::from_gga(parse_field::<u32>(Some(fields[6]))?)
+        .ok_or(SentenceError::UnparsableField)?;
+
+    Ok(Some(PositionFix {
+        latitude: parse_coordinate(fields[2], fields[3], 2)?,
+        longitude: parse_coordinate(fields[4], fields[5], 3)?,
+        orthometric_height: parse_field::<f64>(Some(fields[9]))?,
+        geoid_separation: parse_optional_field::<f64>(Some(fields[11]))?.unwrap_or(0.0),
+        quality,
+        num_satellites: parse_optional_field::<u32>(Some(fields[7]))?.unwrap_or(0),
+        hdop: parse_optional_field::<f64>(Some(fields[8]))?.unwrap_or(f64::INFINITY),
+    }))
+}
+
+/// Extracts the time of day (and date, if the sentence has one) from GGA, RMC
+/// or ZDA. Returns `None` when the receiver has no time yet or no date is known.
+fn find_epoch(header: &str, payload: &str, clock: &mut NmeaClock) -> Result<Option<Epoch>, SentenceError> {
+    let fields: Vec<&str> = payload.split(',').collect();
+
+    let time_str = fields.get(1).ok_or(SentenceError::Truncated)?;
+    if time_str.is_empty() {
+        return Ok(None);
+    }
+    let millis_of_day = parse_time_of_day(time_str)?;
+
+    let date = if is_nmea_command(header, "RMC") {
+        let date_str = fields.get(9).ok_or(SentenceError::Truncated)?;
+        if date_str.is_empty() {
+            None
+        } else {
+            let day = parse_field::<u32>(date_str.get(0..2))?;
+            let month = parse_field::<u32>(date_str.get(2..4))?;
+            let year = parse_field::<i32>(date_str.get(4..6))?;
+            Some(days_from_civil(2000 + year, month, day))
+        }
+    } else if is_nmea_command(header, "ZDA") {
+        let day = parse_optional_field::<u32>(fields.get(2).copied())?;
+        let month = parse_optional_field::<u32>(fields.get(3).copied())?;
+        let year = parse_optional_field::<i32>(fields.get(4).copied())?;
+        match (year, month, day) {
+            (Some(y), Some(m), Some(d)) => Some(days_from_civil(y, m, d)),
+            _ => None,
+        }
+    } else {
+        None
+    };
+
+    Ok(clock.update(date, millis_of_day))
+}
+
+fn parse_gsv_sentence(payload: &str) -> Result<GsvSentence, SentenceError> {
+    let mut fields: Vec<&str> = payload.split(',').collect();
+    if fields.len() < 4 {
+        return Err(SentenceError::Truncated);
+    }
+
+    let header = fields[0];
+    let network = command_to_network(header).ok_or(SentenceError::UnknownTalker)?;
+    let talker = [header.as_bytes()[0], header.as_bytes()[1]];
+
+    let num_messages = parse_field::<usize>(Some(fields[1]))?;
+    let idx_of_message = parse_field::<usize>(Some(fields[2]))?;
+    let num_satellites = parse_field::<usize>(Some(fields[3]))?;
+
+    // NMEA 4.10+ appends a (hexadecimal) signal id after the satellite blocks.
+    let signal_id = if (fields.len() - 4) % 4 == 1 {
+        match fields.pop() {
+            Some("") | None => None,
+            Some(v) => Some(u32::from_str_radix(v, 16).map_err(|_| SentenceError::UnparsableField)?),
+        }
+    } else {
+        None
+    };
+
+    if !(fields.len() - 4).is_multiple_of(4) {
+        return Err(SentenceError::Truncated);
+    }
+
+    let mut satellites = Vec::with_capacity(4);
+    for block in fields[4..].chunks(4) {
+        // Some receivers pad the last sentence of a group with empty blocks.
+        let id = match parse_optional_field::<u32>(Some(block[0]))? {
+            Some(v) => v,
+            None => continue,
+        };
+        satellites.push(GsvSatellite {
+            id,
+            elevation: parse_optional_field::<f64>(Some(block[1]))?,
+            azimuth: parse_optional_field::<f64>(Some(block[2]))?,
+            snr: parse_optional_field::<f64>(Some(block[3]))?,
+        });
+    }
+
+    Ok(GsvSentence {
+        talker,
+        network,
+        num_messages,
+        idx_of_message,
+        num_satellites,
+        signal_id,
+        satellites,
+    })
+}
+
+fn group_to_records_into(group: GsvGroup, config: &Config, report: &mut ParseReport, records: &mut Vec<Record>) {
+    if !group.complete {
+        report.partial_groups += 1;
+        if !config.keep_partial_gsv_groups {
+            return;
+        }
+    }
+
+    // Without a signal id (pre-4.10 receivers) the band cannot be told apart.
+    let signal_id = match group.signal_id {
+        Some(v) => v,
+        None => return,
+    };
+
+    for sat in group.satellites {
+        let (network, satellite) = match normalise_satellite(sat.id, group.network) {
+            Some(v) => v,
+            None => {
+                report.unknown_satellites += 1;
+                continue;
+            }
+        };
+        if !config.networks.contains(&network) {
+            report.excluded_satellites += 1;
+            continue;
+        }
+        let band = signal_to_band(network, signal_id);
+
+        // Satellites without a position or that are not tracked carry empty fields.
+        let (elevation, azimuth, snr) = match (sat.elevation, sat.azimuth, sat.snr) {
+            (Some(e), Some(a), Some(s)) => (e, a, s),
+            _ => continue,
+        };
+
+        if !config.accepts_geometry(elevation, azimuth) {
+            continue;
+        }
+
+        records.push(Record {
+            id: Record::make_id(network, band, satellite),
+            satellite,
+            elevation,
+            azimuth,
+            snr,
+            time: group.time,
+            network,
+            band,
+            quality: Quality::default(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Frames a payload as a sentence with its checksum.
+    fn sentence(payload: &str) -> String {
+        let checksum = payload.bytes().fold(0u8, |acc, b| acc ^ b);
+        format!("${}*{:02X}", payload, checksum)
+    }
+
+    /// Feeds payloads, framed as sentences, through a parser.
+    fn parse(payloads: &[&str]) -> (Vec<Record>, ParseReport) {
+        let mut parser = NmeaParser::new(&Config::default());
+        let mut records = Vec::new();
+        for payload in payloads {
+            parser.push_sentence(&sentence(payload), &mut records);
+        }
+        parser.finish(&mut records);
+        (records, parser.report().clone())
+    }
+
+    #[test]
+    fn talker_check_and_network_agree() {
+        for (talker, network) in TALKERS {
+            let header = format!("{}GSV", talker);
+            assert!(is_known_talker(&header));
+            assert_eq!(command_to_network(&header), Some(network));
+        }
+        assert_eq!(command_to_network("BDGSV"), Some(Network::BeiDou));
+        assert_eq!(command_to_network("XXGSV"), None);
+        assert_eq!(verify_nmea_sentence(&sentence("XXGSV,1,1,00")), Err(SentenceError::UnknownTalker));
+    }
+
+    #[test]
+    fn overlong_line_is_dropped_whole() {
+        let mut parser = NmeaParser::new(&Config::default());
+        let mut records = Vec::new();
+        let rmc = sentence("GPRMC,120000.00,A,5159.91,N,00422.40,E,0.0,0.0,160925,,,A");
+        let line = format!("{}{}\n", rmc, " ".repeat(MAX_LINE_LENGTH));
+        parser.push_bytes(line.as_bytes(), &mut records);
+        parser.push_bytes(format!("{}\n", rmc).as_bytes(), &mut records);
+        assert_eq!(parser.report().not_nmea, 1);
+        assert_eq!(parser.report().accepted, 1);
+    }
+
+    #[test]
+    fn bad_checksum_is_counted() {
+        // A wrong checksum, then corrupted bytes after the `*` as a noisy serial line gives them.
+        let stream = format!("$GPGGA,120000.00,,,,,0,00,,,M,,M,,*00\n$GPGGA,1*aé\n$GPGGA,1*+5\n$GPGGA,1*é\n{}\n", sentence("GPé"));
+        let mut parser = NmeaParser::new(&Config::default());
+        let mut records = Vec::new();
+        parser.push_bytes(stream.as_bytes(), &mut records);
+        parser.finish(&mut records);
+        let report = parser.report();
+        assert!(records.is_empty());
+        assert_eq!(report.bad_checksum, 4);
+        assert_eq!(report.ignored, 1);
+        assert_eq!(report.rejected(), 4);
+    }
+
+    #[test]
+    fn gga_with_broken_position_still_sets_the_epoch() {
+        let (records, report) = parse(&[
+            "GPRMC,120000.00,A,5159.91,N,00422.40,E,0.0,0.0,160925,,,A",
+            "GPGSV,1,1,01,03,05,120,40,1",
+            "GPGGA,120001.00,51x9.91,N,00422.40,E,1,08,0.9,10.0,M,47.0,M,,",
+            "GPGSV,1,1,01,03,05,120,41,1",
+        ]);
+
+        assert_eq!(report.unparsable_field, 1);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].time - records[0].time, 1.0);
+    }
+
+    #[test]
+    fn station_position_from_gga() {
+        let mut parser = NmeaParser::new(&Config::default());
+        let mut records = Vec::new();
+        for payload in [
+            "GPGGA,120000.00,5159.91580,N,00422.40128,E,1,08,0.9,27.7,M,47.0,M,,",
+            "GPGGA,120001.00,5159.91580,N,00422.40128,E,1,08,0.9,27.7,M,47.0,M,,",
+            "GPGGA,120002.00,5159.91580,N,00422.40128,E,1,08,0.9,27.7,M,47.0,M,,",
+            // About 1 km north: an outlier.
+            "GPGGA,120003.00,5200.45580,N,00422.40128,E,1,08,0.9,27.7,M,47.0,M,,",
+            // HDOP above `max_position_hdop`.
+            "GPGGA,120004.00,5159.91580,N,00422.40128,E,1,08,9.0,27.7,M,47.0,M,,",
+        ] {
+            parser.push_sentence(&sentence(payload), &mut records);
+        }
+
+        let position = parser.station_metadata().position.unwrap();
+        assert!((position.latitude - (51.0 + 59.9158 / 60.0)).abs() < 1e-9);
+        assert!((position.longitude - (4.0 + 22.40128 / 60.0)).abs() < 1e-9);
+        assert!((position.ellipsoidal_height - 74.7).abs() < 1e-9);
+        assert!((position.orthometric_height.unwrap() - 27.7).abs() < 1e-9);
+        assert_eq!((position.samples_used, position.samples_rejected), (3, 2));
+    }
+
+    #[test]
+    fn one_signal_per_band_and_epoch() {
+        let (records, report) = parse(&[
+            "GPRMC,120000.00,A,5159.91,N,00422.40,E,0.0,0.0,160925,,,A",
+            // L5-I (7) and L5-Q (8) of G03 share the L5 band.
+            "GPGSV,1,1,01,03,05,120,40,7",
+            "GPGSV,1,1,01,03,05,120,44,8",
+        ]);
+
+        assert_eq!(records.len(), 1);
+        assert_eq!((records[0].band, records[0].snr), (Band::L5, 40.0));
+        assert_eq!(report.duplicate_signals, 1);
+    }
+
+    #[test]
+    fn beidou_and_combined_groups_keep_their_networks() {
+        let (records, report) = parse(&[
+            "GPRMC,120000.00,A,5159.91,N,00422.40,E,0.0,0.0,160925,,,A",
+            "BDGSV,1,1,01,07,05,120,40,1",
+            "GNGSV,1,1,02,07,05,120,40,70,05,200,38,1",
+        ]);
+
+        assert_eq!(records.len(), 2);
+        assert_eq!((records[0].network, records[0].satellite, records[0].band), (Network::BeiDou, 7, Band::B1I));
+        assert_eq!((records[1].network, records[1].satellite, records[1].band), (Network::GLONASS, 6, Band::G1));
+        // Satellite 07 of the GN group could belong to any constellation.
+        assert_eq!(report.unknown_satellites, 1);
+    }
+}
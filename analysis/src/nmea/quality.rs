@@ -0,0 +1,184 @@
+use std::collections::HashSet;
+
+use crate::db::record::{FixMode, Network, Quality, Record};
+use super::{command_to_network, normalise_satellite, parse_field, parse_optional_field, SentenceError};
+
+/// Quality information gathered from the GSA, GST and GBS sentences of one epoch.
+#[derive(Debug, Default)]
+pub struct EpochQuality {
+    fix_mode: FixMode,
+    pdop: Option<f64>,
+    hdop: Option<f64>,
+    vdop: Option<f64>,
+    range_rms: Option<f64>,
+    position_std: Option<f64>,
+    seen_gsa: bool,
+    used: HashSet<(Network, u32)>,
+    faulty: HashSet<(Network, u32)>,
+}
+
+/// Maps the NMEA 4.10 GNSS system id of GSA/GBS to a network.
+fn system_to_network(system_id: u32) -> Option<Network> {
+    let network = match system_id {
+        1 => Network::GPS,
+        2 => Network::GLONASS,
+        3 => Network::Galileo,
+        4 => Network::BeiDou,
+        5 => Network::QZSS,
+        6 => Network::NavIC,
+        _ => return None,
+    };
+    Some(network)
+}
+
+/// Network of a GSA/GBS sentence: from its system id if present, else from the talker.
+fn sentence_network(header: &str, system_field: Option<&str>) -> Result<Network, SentenceError> {
+    match system_field {
+        Some(v) if !v.is_empty() => system_to_network(parse_field::<u32>(Some(v))?).ok_or(SentenceError::UnparsableField),
+        _ => command_to_network(header).ok_or(SentenceError::UnknownTalker),
+    }
+}
+
+impl EpochQuality {
+    pub fn push_gsa(&mut self, payload: &str) -> Result<(), SentenceError> {
+        let fields: Vec<&str> = payload.split(',').collect();
+        if fields.len() < 18 {
+            return Err(SentenceError::Truncated);
+        }
+
+        let network = sentence_network(fields[0], fields.get(18).copied())?;
+
+        self.fix_mode = match parse_field::<u32>(Some(fields[2]))? {
+            1 => FixMode::NoFix,
+            2 => FixMode::Fix2D,
+            3 => FixMode::Fix3D,
+            _ => return Err(SentenceError::UnparsableField),
+        };
+        self.pdop = parse_optional_field::<f64>(Some(fields[15]))?;
+        self.hdop = parse_optional_field::<f64>(Some(fields[16]))?;
+        self.vdop = parse_optional_field::<f64>(Some(fields[17]))?;
+        self.seen_gsa = true;
+
+        for field in &fields[3..15] {
+            let Some(id) = parse_optional_field::<u32>(Some(field))? else {
+                continue;
+            };
+            let satellite = match (network, id) {
+                // A GNGSA without system id (before NMEA 4.10) gives GPS satellites by PRN.
+                (Network::Unknown, 1..=32) => Some((Network::GPS, id)),
+                _ => normalise_satellite(id, network),
+            };
+            if let Some(satellite) = satellite {
+                self.used.insert(satellite);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn push_gst(&mut self, payload: &str) -> Result<(), SentenceError> {
+        let fields: Vec<&str> = payload.split(',').collect();
+        if fields.len() < 9 {
+            return Err(SentenceError::Truncated);
+        }
+
+        self.range_rms = parse_optional_field::<f64>(Some(fields[2]))?;
+        let lat = parse_optional_field::<f64>(Some(fields[6]))?;
+        let lon = parse_optional_field::<f64>(Some(fields[7]))?;
+        let alt = parse_optional_field::<f64>(Some(fields[8]))?;
+        self.position_std = match (lat, lon, alt) {
+            (Some(lat), Some(lon), Some(alt)) => Some((lat * lat + lon * lon + alt * alt).sqrt()),
+            _ => None,
+        };
+
+        Ok(())
+    }
+
+    pub fn push_gbs(&mut self, payload: &str) -> Result<(), SentenceError> {
+        let fields: Vec<&str> = payload.split(',').collect();
+        if fields.len() < 9 {
+            return Err(SentenceError::Truncated);
+        }
+
+        let network = sentence_network(fields[0], fields.get(9).copied())?;
+        if let Some(id) = parse_optional_field::<u32>(Some(fields[5]))?
+            && let Some(satellite) = normalise_satellite(id, network)
+        {
+            self.faulty.insert(satellite);
+        }
+
+        Ok(())
+    }
+
+    pub fn apply(&self, record: &mut Record) {
+        let key = (record.network, record.satellite);
+        record.quality = Quality {
+            fix_mode: self.fix_mode,
+            pdop: self.pdop,
+            hdop: self.hdop,
+            vdop: self.vdop,
+            range_rms: self.range_rms,
+            position_std: self.position_std,
+            in_use: if self.seen_gsa { Some(self.used.contains(&key)) } else { None },
+            faulty: self.faulty.contains(&key),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::record::test_record;
+    use crate::time::Epoch;
+
+    fn record(network: Network, satellite: u32) -> Record {
+        test_record(network, satellite, Epoch::from_unix_utc_millis(1_700_000_000_000))
+    }
+
+    #[test]
+    fn gsa_gst_gbs_fill_the_record_quality() {
+        let mut quality = EpochQuality::default();
+        quality.push_gsa("GNGSA,A,3,05,12,,,,,,,,,,,1.8,0.9,1.5,1").unwrap();
+        quality.push_gsa("GNGSA,A,3,71,,,,,,,,,,,,1.8,0.9,1.5,2").unwrap();
+        quality.push_gst("GNGST,120000.00,2.5,,,,3.0,4.0,12.0").unwrap();
+        quality.push_gbs("GNGBS,120000.00,3.0,4.0,12.0,12,,,,1").unwrap();
+
+        let mut used = record(Network::GPS, 12);
+        quality.apply(&mut used);
+        assert_eq!(used.quality.fix_mode, FixMode::Fix3D);
+        assert_eq!((used.quality.pdop, used.quality.hdop, used.quality.vdop), (Some(1.8), Some(0.9), Some(1.5)));
+        assert_eq!(used.quality.range_rms, Some(2.5));
+        assert_eq!(used.quality.position_std, Some(13.0));
+        assert_eq!(used.quality.in_use, Some(true));
+        assert!(used.quality.faulty);
+
+        // GPS 07 is not in the GPS GSA; GLONASS 07 (NMEA id 71) is in the GLONASS one.
+        let mut unused = record(Network::GPS, 7);
+        quality.apply(&mut unused);
+        assert_eq!(unused.quality.in_use, Some(false));
+        assert!(!unused.quality.faulty);
+        let mut glonass = record(Network::GLONASS, 7);
+        quality.apply(&mut glonass);
+        assert_eq!(glonass.quality.in_use, Some(true));
+    }
+
+    #[test]
+    fn without_gsa_use_is_unknown() {
+        let mut rec = record(Network::GPS, 12);
+        EpochQuality::default().apply(&mut rec);
+        assert_eq!(rec.quality.in_use, None);
+        assert_eq!(rec.quality.fix_mode, FixMode::Unknown);
+    }
+
+    #[test]
+    fn combined_gsa_without_system_id_marks_gps_in_use() {
+        let mut quality = EpochQuality::default();
+        quality.push_gsa("GNGSA,A,3,05,12,71,,,,,,,,,,1.8,0.9,1.5").unwrap();
+
+        for (network, satellite, in_use) in [(Network::GPS, 5, true), (Network::GPS, 12, true), (Network::GLONASS, 7, true), (Network::GPS, 7, false)] {
+            let mut rec = record(network, satellite);
+            quality.apply(&mut rec);
+            assert_eq!(rec.quality.in_use, Some(in_use), "{:?} {}", network, satellite);
+        }
+    }
+}
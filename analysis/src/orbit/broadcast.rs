@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+
+use crate::db::record::Network;
+use crate::time::Epoch;
+
+use super::GeometrySource;
+use super::glonass::GlonassEphemeris;
+use super::kepler::KeplerEphemeris;
+
+/// All broadcast ephemerides of a navigation file, per satellite.
+#[derive(Debug, Clone, Default)]
+pub struct BroadcastOrbits {
+    kepler: HashMap<(Network, u32), Vec<KeplerEphemeris>>,
+    glonass: HashMap<u32, Vec<GlonassEphemeris>>,
+}
+
+impl BroadcastOrbits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an ephemeris; unhealthy ones are kept out.
+    pub fn push_kepler(&mut self, ephemeris: KeplerEphemeris) {
+        if ephemeris.healthy {
+            self.kepler.entry((ephemeris.network, ephemeris.satellite)).or_default().push(ephemeris);
+        }
+    }
+
+    pub fn push_glonass(&mut self, ephemeris: GlonassEphemeris) {
+        if ephemeris.healthy {
+            self.glonass.entry(ephemeris.satellite).or_default().push(ephemeris);
+        }
+    }
+
+    /// Number of healthy ephemerides.
+    pub fn len(&self) -> usize {
+        self.kepler.values().map(Vec::len).sum::<usize>() + self.glonass.values().map(Vec::len).sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of satellites with at least one healthy ephemeris.
+    pub fn satellites(&self) -> usize {
+        self.kepler.len() + self.glonass.len()
+    }
+}
+
+impl GeometrySource for BroadcastOrbits {
+    /// Uses the ephemeris with the reference epoch nearest to `time`, if that
+    /// is recent enough for the constellation.
+    fn satellite_position(&self, network: Network, satellite: u32, time: Epoch) -> Option<(f64, f64, f64)> {
+        if network == Network::GLONASS {
+            let ephemeris = self.glonass.get(&satellite)?.iter()
+                .min_by(|a, b| (time - a.tb).abs().total_cmp(&(time - b.tb).abs()))?;
+            if (time - ephemeris.tb).abs() > ephemeris.max_age() {
+                return None;
+            }
+            return Some(ephemeris.position(time));
+        }
+
+        let ephemeris = self.kepler.get(&(network, satellite))?.iter()
+            .min_by(|a, b| (time - a.toe).abs().total_cmp(&(time - b.toe).abs()))?;
+        if (time - ephemeris.toe).abs() > ephemeris.max_age() {
+            return None;
+        }
+        Some(ephemeris.position(time))
+    }
+}
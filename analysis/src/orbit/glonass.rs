@@ -0,0 +1,105 @@
+use crate::time::Epoch;
+
+/// PZ-90 constants of the GLONASS ICD: gravitational constant (m^3/s^2),
+/// semi-major axis (m), second zonal harmonic and Earth rotation rate (rad/s).
+const GM: f64 = 3.986_004_418e14;
+const A_E: f64 = 6_378_136.0;
+const J2: f64 = 1.082_625_75e-3;
+const OMEGA_E: f64 = 7.292_115e-5;
+
+/// Longest integration step (s).
+const MAX_STEP: f64 = 60.0;
+
+/// Longest time (s) from the reference epoch an ephemeris is used for.
+const MAX_AGE: f64 = 1800.0;
+
+/// Broadcast state vector of a GLONASS satellite, in metres and seconds.
+#[derive(Debug, Clone)]
+pub struct GlonassEphemeris {
+    pub satellite: u32,
+    /// Reference epoch `tb` of the state vector.
+    pub tb: Epoch,
+    pub position: [f64; 3],
+    pub velocity: [f64; 3],
+    /// Lunisolar acceleration, taken as constant over the interval.
+    pub acceleration: [f64; 3],
+    pub healthy: bool,
+}
+
+impl GlonassEphemeris {
+    pub fn max_age(&self) -> f64 {
+        MAX_AGE
+    }
+
+    /// Derivative of the state (position, velocity) in the rotating frame.
+    fn derivative(&self, state: &[f64; 6]) -> [f64; 6] {
+        let [x, y, z, vx, vy, vz] = *state;
+        let r2 = x * x + y * y + z * z;
+        let r = r2.sqrt();
+        let mu = GM / (r2 * r);
+        let j2 = 1.5 * J2 * GM * A_E * A_E / (r2 * r2 * r);
+        let z2 = 5.0 * z * z / r2;
+        let [ax, ay, az] = self.acceleration;
+
+        [
+            vx,
+            vy,
+            vz,
+            -mu * x - j2 * x * (1.0 - z2) + OMEGA_E * OMEGA_E * x + 2.0 * OMEGA_E * vy + ax,
+            -mu * y - j2 * y * (1.0 - z2) + OMEGA_E * OMEGA_E * y - 2.0 * OMEGA_E * vx + ay,
+            -mu * z - j2 * z * (3.0 - z2) + az,
+        ]
+    }
+
+    /// ECEF position (m) at `time`, integrating the equations of motion of
+    /// the GLONASS ICD with a fourth-order Runge-Kutta scheme.
+    pub fn position(&self, time: Epoch) -> (f64, f64, f64) {
+        let span = time - self.tb;
+        let steps = (span.abs() / MAX_STEP).ceil().max(1.0);
+        let h = span / steps;
+
+        let mut state = [
+            self.position[0], self.position[1], self.position[2],
+            self.velocity[0], self.velocity[1], self.velocity[2],
+        ];
+        let add = |s: &[f64; 6], k: &[f64; 6], f: f64| -> [f64; 6] {
+            std::array::from_fn(|i| s[i] + k[i] * f)
+        };
+
+        for _ in 0..steps as usize {
+            let k1 = self.derivative(&state);
+            let k2 = self.derivative(&add(&state, &k1, h / 2.0));
+            let k3 = self.derivative(&add(&state, &k2, h / 2.0));
+            let k4 = self.derivative(&add(&state, &k3, h));
+            state = std::array::from_fn(|i| state[i] + h / 6.0 * (k1[i] + 2.0 * k2[i] + 2.0 * k3[i] + k4[i]));
+        }
+
+        (state[0], state[1], state[2])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn icd_example() {
+        // The worked example of the GLONASS ICD (edition 5.1, appendix
+        // A.3.1.2): the state at tb = 11700 s propagated to 12300 s.
+        let tb = Epoch::from_gps_week_millis(2000, 11_700_000);
+        let ephemeris = GlonassEphemeris {
+            satellite: 1,
+            tb,
+            position: [7_003_008.789, -12_206_626.953, 21_280_765.625],
+            velocity: [783.5417, 2804.2530, 1352.5150],
+            acceleration: [0.0, 1.7e-6, -5.41e-6],
+            healthy: true,
+        };
+        assert_eq!(ephemeris.position(tb), (7_003_008.789, -12_206_626.953, 21_280_765.625));
+
+        let (x, y, z) = ephemeris.position(tb + 600.0);
+        assert!((x - 7_523_174.853).abs() < 2.0, "x {}", x);
+        assert!((y + 10_506_962.176).abs() < 2.0, "y {}", y);
+        assert!((z - 21_999_239.866).abs() < 2.0, "z {}", z);
+    }
+}
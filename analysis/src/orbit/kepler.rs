@@ -0,0 +1,173 @@
+use crate::db::record::Network;
+use crate::time::Epoch;
+
+/// Earth's gravitational constant (m^3/s^2) as used by GPS and by
+/// Galileo/BeiDou, and the Earth rotation rate (rad/s) of GPS and BeiDou.
+const GM_GPS: f64 = 3.986_005e14;
+const GM_GALILEO_BEIDOU: f64 = 3.986_004_418e14;
+const OMEGA_E_GPS: f64 = 7.292_115_146_7e-5;
+const OMEGA_E_BEIDOU: f64 = 7.292_115e-5;
+
+/// Longest time (s) from the reference epoch an ephemeris is used for.
+const MAX_AGE: f64 = 7200.0;
+const MAX_AGE_BEIDOU: f64 = 3600.0;
+
+/// Broadcast Keplerian elements of a GPS, Galileo, BeiDou, QZSS or NavIC satellite.
+#[derive(Debug, Clone)]
+pub struct KeplerEphemeris {
+    pub network: Network,
+    pub satellite: u32,
+    /// Reference epoch of the elements.
+    pub toe: Epoch,
+    /// The same epoch in seconds of the week of the satellite's own time scale.
+    pub toe_seconds: f64,
+    pub sqrt_a: f64,
+    pub e: f64,
+    pub i0: f64,
+    pub omega0: f64,
+    pub omega: f64,
+    pub m0: f64,
+    pub delta_n: f64,
+    pub omega_dot: f64,
+    pub idot: f64,
+    pub cuc: f64,
+    pub cus: f64,
+    pub crc: f64,
+    pub crs: f64,
+    pub cic: f64,
+    pub cis: f64,
+    pub healthy: bool,
+}
+
+impl KeplerEphemeris {
+    pub fn max_age(&self) -> f64 {
+        match self.network {
+            Network::BeiDou => MAX_AGE_BEIDOU,
+            _ => MAX_AGE,
+        }
+    }
+
+    /// BeiDou satellites in geostationary orbit, whose elements are given in
+    /// an inclined reference frame.
+    fn is_beidou_geo(&self) -> bool {
+        self.network == Network::BeiDou && (self.satellite <= 5 || self.satellite >= 59)
+    }
+
+    /// ECEF position (m) at `time`, following IS-GPS-200 section 20.3.3.4.3.
+    pub fn position(&self, time: Epoch) -> (f64, f64, f64) {
+        let (gm, omega_e) = match self.network {
+            Network::GPS | Network::QZSS | Network::NavIC => (GM_GPS, OMEGA_E_GPS),
+            Network::BeiDou => (GM_GALILEO_BEIDOU, OMEGA_E_BEIDOU),
+            _ => (GM_GALILEO_BEIDOU, OMEGA_E_GPS),
+        };
+
+        let a = self.sqrt_a * self.sqrt_a;
+        let tk = time - self.toe;
+        let n = (gm / (a * a * a)).sqrt() + self.delta_n;
+        let m = self.m0 + n * tk;
+
+        let mut e_anomaly = m;
+        for _ in 0..10 {
+            let next = m + self.e * e_anomaly.sin();
+            if (next - e_anomaly).abs() < 1e-13 {
+                e_anomaly = next;
+                break;
+            }
+            e_anomaly = next;
+        }
+
+        let nu = ((1.0 - self.e * self.e).sqrt() * e_anomaly.sin()).atan2(e_anomaly.cos() - self.e);
+        let phi = nu + self.omega;
+        let (sin2, cos2) = (2.0 * phi).sin_cos();
+        let u = phi + self.cus * sin2 + self.cuc * cos2;
+        let r = a * (1.0 - self.e * e_anomaly.cos()) + self.crs * sin2 + self.crc * cos2;
+        let i = self.i0 + self.cis * sin2 + self.cic * cos2 + self.idot * tk;
+
+        let (x_orbit, y_orbit) = (r * u.cos(), r * u.sin());
+
+        if self.is_beidou_geo() {
+            let node = self.omega0 + self.omega_dot * tk - omega_e * self.toe_seconds;
+            let x = x_orbit * node.cos() - y_orbit * i.cos() * node.sin();
+            let y = x_orbit * node.sin() + y_orbit * i.cos() * node.cos();
+            let z = y_orbit * i.sin();
+
+            // Rotate by -5 degrees about x, then by the Earth rotation since toe about z.
+            let (sin_x, cos_x) = (-5.0f64).to_radians().sin_cos();
+            let (sin_z, cos_z) = (omega_e * tk).sin_cos();
+            let y_tilted = y * cos_x + z * sin_x;
+            let z_tilted = -y * sin_x + z * cos_x;
+            return (x * cos_z + y_tilted * sin_z, -x * sin_z + y_tilted * cos_z, z_tilted);
+        }
+
+        let node = self.omega0 + (self.omega_dot - omega_e) * tk - omega_e * self.toe_seconds;
+        (
+            x_orbit * node.cos() - y_orbit * i.cos() * node.sin(),
+            x_orbit * node.sin() + y_orbit * i.cos() * node.cos(),
+            y_orbit * i.sin(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: f64 = 26_560_000.0;
+
+    /// Circular, equatorial GPS orbit with the node on the x axis at toe.
+    fn ephemeris() -> KeplerEphemeris {
+        KeplerEphemeris {
+            network: Network::GPS,
+            satellite: 1,
+            toe: Epoch::from_gps_week_millis(2000, 0),
+            toe_seconds: 0.0,
+            sqrt_a: A.sqrt(),
+            e: 0.0,
+            i0: 0.0,
+            omega0: 0.0,
+            omega: 0.0,
+            m0: 0.0,
+            delta_n: 0.0,
+            omega_dot: 0.0,
+            idot: 0.0,
+            cuc: 0.0,
+            cus: 0.0,
+            crc: 0.0,
+            crs: 0.0,
+            cic: 0.0,
+            cis: 0.0,
+            healthy: true,
+        }
+    }
+
+    fn assert_close(actual: (f64, f64, f64), expected: (f64, f64, f64)) {
+        let distance = ((actual.0 - expected.0).powi(2) + (actual.1 - expected.1).powi(2) + (actual.2 - expected.2).powi(2)).sqrt();
+        assert!(distance < 1e-3, "{:?} is {} m from {:?}", actual, distance, expected);
+    }
+
+    #[test]
+    fn circular_orbit_against_earth_rotation() {
+        let eph = ephemeris();
+        assert_close(eph.position(eph.toe), (A, 0.0, 0.0));
+
+        // Half an hour later the satellite has moved on by n t, the Earth by omega_e t.
+        let angle = ((GM_GPS / A.powi(3)).sqrt() - OMEGA_E_GPS) * 1800.0;
+        assert_close(eph.position(eph.toe + 1800.0), (A * angle.cos(), A * angle.sin(), 0.0));
+    }
+
+    #[test]
+    fn inclined_orbit_at_its_northern_point() {
+        let eph = KeplerEphemeris { i0: 55f64.to_radians(), m0: std::f64::consts::FRAC_PI_2, ..ephemeris() };
+        let i = 55f64.to_radians();
+        assert_close(eph.position(eph.toe), (0.0, A * i.cos(), A * i.sin()));
+    }
+
+    #[test]
+    fn eccentric_orbit_at_perigee_and_apogee() {
+        let e = 0.02;
+        let perigee = KeplerEphemeris { e, crc: 50.0, ..ephemeris() };
+        assert_close(perigee.position(perigee.toe), (A * (1.0 - e) + 50.0, 0.0, 0.0));
+        let apogee = KeplerEphemeris { e, m0: std::f64::consts::PI, ..ephemeris() };
+        assert_close(apogee.position(apogee.toe), (-A * (1.0 + e), 0.0, 0.0));
+    }
+}
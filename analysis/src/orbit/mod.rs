@@ -0,0 +1,138 @@
+pub mod broadcast;
+pub mod glonass;
+pub mod kepler;
+pub mod precise;
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::config::Config;
+use crate::db::record::{Network, Record};
+use crate::station::StationPosition;
+use crate::time::Epoch;
+
+pub use broadcast::BroadcastOrbits;
+pub use precise::PreciseOrbits;
+
+const SPEED_OF_LIGHT: f64 = 299_792_458.0;
+const OMEGA_E: f64 = 7.292_115_146_7e-5;
+
+/// Where the elevation and azimuth of the records come from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrbitSource {
+    /// As reported by the receiver (whole degrees for NMEA GSV).
+    Receiver,
+    /// Computed from the broadcast ephemerides of a RINEX 3 navigation file.
+    Broadcast(String),
+    /// Interpolated from SP3 precise orbit files, e.g. of consecutive days.
+    Precise(Vec<String>),
+}
+
+/// Anything that can tell where a satellite is.
+pub trait GeometrySource {
+    /// ECEF position (m) of the satellite at `time` (GPS time), or None when
+    /// no orbit covers it.
+    fn satellite_position(&self, network: Network, satellite: u32, time: Epoch) -> Option<(f64, f64, f64)>;
+}
+
+/// Elevation and azimuth (degrees) of a satellite seen from a receiver, both
+/// in ECEF, with the receiver's geodetic latitude and longitude (degrees).
+pub fn look_angles(latitude: f64, longitude: f64, receiver: (f64, f64, f64), satellite: (f64, f64, f64)) -> (f64, f64) {
+    let (dx, dy, dz) = (satellite.0 - receiver.0, satellite.1 - receiver.1, satellite.2 - receiver.2);
+    let (sin_phi, cos_phi) = latitude.to_radians().sin_cos();
+    let (sin_lambda, cos_lambda) = longitude.to_radians().sin_cos();
+
+    let east = -sin_lambda * dx + cos_lambda * dy;
+    let north = -sin_phi * cos_lambda * dx - sin_phi * sin_lambda * dy + cos_phi * dz;
+    let up = cos_phi * cos_lambda * dx + cos_phi * sin_lambda * dy + sin_phi * dz;
+
+    let elevation = up.atan2(east.hypot(north)).to_degrees();
+    let azimuth = east.atan2(north).to_degrees().rem_euclid(360.0);
+    (elevation, azimuth)
+}
+
+/// Satellite position at the moment the signal received at `time` left it,
+/// in the Earth-fixed frame at reception (light time and Sagnac effect).
+fn transmit_position(
+    source: &dyn GeometrySource,
+    network: Network,
+    satellite: u32,
+    time: Epoch,
+    receiver: (f64, f64, f64),
+) -> Option<(f64, f64, f64)> {
+    let mut travel = 0.075;
+    let mut position = (0.0, 0.0, 0.0);
+    for _ in 0..3 {
+        let (x, y, z) = source.satellite_position(network, satellite, time - travel)?;
+        let (sin, cos) = (OMEGA_E * travel).sin_cos();
+        position = (x * cos + y * sin, -x * sin + y * cos, z);
+        let range = ((position.0 - receiver.0).powi(2) + (position.1 - receiver.1).powi(2) + (position.2 - receiver.2).powi(2)).sqrt();
+        travel = range / SPEED_OF_LIGHT;
+    }
+    Some(position)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GeometryReport {
+    /// Records whose elevation and azimuth were computed from the orbits.
+    pub computed: usize,
+    /// Records without an orbit that kept the receiver's elevation and azimuth.
+    pub kept: usize,
+    /// Records without an orbit nor receiver geometry, dropped.
+    pub without_orbit: usize,
+    /// Records dropped as the computed geometry is outside the configured window.
+    pub outside_window: usize,
+}
+
+impl fmt::Display for GeometryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} records with computed geometry, {} kept receiver geometry, {} dropped without orbit, {} dropped outside the elevation/azimuth window",
+            self.computed, self.kept, self.without_orbit, self.outside_window,
+        )
+    }
+}
+
+/// Replaces the elevation and azimuth of every record by those computed from
+/// `source` and the station position, then drops the records outside the
+/// elevation/azimuth window of `config`. Records the source has no orbit for
+/// keep the geometry they came with, if any.
+pub fn apply_geometry(records: &mut Vec<Record>, source: &dyn GeometrySource, station: &StationPosition, config: &Config) -> GeometryReport {
+    let mut report = GeometryReport::default();
+    let receiver = station.ecef();
+
+    // Signals of the same satellite share the geometry of each epoch.
+    let mut cache: HashMap<(Network, u32, Epoch), Option<(f64, f64)>> = HashMap::new();
+
+    records.retain_mut(|record| {
+        let geometry = *cache.entry((record.network, record.satellite, record.time)).or_insert_with(|| {
+            transmit_position(source, record.network, record.satellite, record.time, receiver)
+                .map(|satellite| look_angles(station.latitude, station.longitude, receiver, satellite))
+        });
+
+        match geometry {
+            Some((elevation, azimuth)) => {
+                record.elevation = elevation;
+                record.azimuth = azimuth;
+                report.computed += 1;
+            }
+            None if record.elevation.is_finite() && record.azimuth.is_finite() => {
+                report.kept += 1;
+                return true;
+            }
+            None => {
+                report.without_orbit += 1;
+                return false;
+            }
+        }
+
+        if !config.accepts_geometry(record.elevation, record.azimuth) {
+            report.outside_window += 1;
+            return false;
+        }
+        true
+    });
+
+    report
+}
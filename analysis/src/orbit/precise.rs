@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+
+use crate::db::record::Network;
+use crate::time::Epoch;
+
+use super::GeometrySource;
+
+/// Samples used for each interpolation, a 9th order polynomial as usual for
+/// 15 minute IGS orbits.
+const INTERPOLATION_POINTS: usize = 10;
+
+/// Samples of one satellite further apart than this many nominal intervals
+/// count as a gap, which an interpolation window must not span.
+const MAX_GAP_INTERVALS: f64 = 1.5;
+
+/// Positions (m) of one satellite, sorted by time.
+type Samples = Vec<(Epoch, [f64; 3])>;
+
+/// Tabulated precise satellite positions, e.g. from SP3 files.
+#[derive(Debug, Clone, Default)]
+pub struct PreciseOrbits {
+    samples: HashMap<(Network, u32), Samples>,
+    /// Nominal spacing of the samples (s).
+    interval: f64,
+}
+
+impl PreciseOrbits {
+    pub fn new(interval: f64) -> Self {
+        PreciseOrbits { samples: HashMap::new(), interval }
+    }
+
+    pub fn push(&mut self, network: Network, satellite: u32, time: Epoch, position: [f64; 3]) {
+        self.samples.entry((network, satellite)).or_default().push((time, position));
+    }
+
+    /// Adds the samples of another file, e.g. the previous and next day so
+    /// that interpolation works up to midnight.
+    pub fn merge(&mut self, other: PreciseOrbits) {
+        for (key, samples) in other.samples {
+            self.samples.entry(key).or_default().extend(samples);
+        }
+        if self.interval == 0.0 {
+            self.interval = other.interval;
+        }
+        self.sort();
+    }
+
+    /// Sorts the samples by time, dropping duplicated epochs.
+    pub fn sort(&mut self) {
+        for samples in self.samples.values_mut() {
+            samples.sort_by_key(|(time, _)| *time);
+            samples.dedup_by_key(|(time, _)| *time);
+        }
+    }
+
+    pub fn satellites(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn interval(&self) -> f64 {
+        self.interval
+    }
+}
+
+impl GeometrySource for PreciseOrbits {
+    /// Lagrange interpolation over the samples around `time`. Nothing is
+    /// extrapolated beyond the samples or across gaps.
+    fn satellite_position(&self, network: Network, satellite: u32, time: Epoch) -> Option<(f64, f64, f64)> {
+        let samples = self.samples.get(&(network, satellite))?;
+        if samples.len() < INTERPOLATION_POINTS {
+            return None;
+        }
+        if time < samples[0].0 || time > samples[samples.len() - 1].0 {
+            return None;
+        }
+
+        let next = samples.partition_point(|(t, _)| *t < time);
+        let start = next.saturating_sub(INTERPOLATION_POINTS / 2).min(samples.len() - INTERPOLATION_POINTS);
+        let window = &samples[start..start + INTERPOLATION_POINTS];
+
+        let max_gap = MAX_GAP_INTERVALS * self.interval;
+        if self.interval > 0.0 && window.windows(2).any(|pair| pair[1].0 - pair[0].0 > max_gap) {
+            return None;
+        }
+
+        // Times relative to `time`, which keeps the products well conditioned.
+        let offsets: Vec<f64> = window.iter().map(|(t, _)| *t - time).collect();
+        let mut position = [0.0; 3];
+        for (i, (_, sample)) in window.iter().enumerate() {
+            let weight = offsets.iter().enumerate()
+                .filter(|&(j, _)| j != i)
+                .map(|(_, &tj)| -tj / (offsets[i] - tj))
+                .product::<f64>();
+            for (axis, value) in position.iter_mut().zip(sample) {
+                *axis += weight * value;
+            }
+        }
+        Some((position[0], position[1], position[2]))
+    }
+}
@@ -0,0 +1,140 @@
+use crate::station::StationMetadata;
+
+/// Conditions the Bennett formula is given for.
+const STANDARD_PRESSURE: f64 = 1010.0;
+const STANDARD_TEMPERATURE: f64 = 10.0;
+
+/// Below this geometric elevation (degrees) the formula is no longer valid
+/// and the elevation is left alone.
+const MIN_ELEVATION: f64 = -1.0;
+
+/// How satellite elevation angles are corrected for tropospheric bending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefractionModel {
+    None,
+    /// Bennett-style formula for standard conditions, 1010 hPa and 10 °C.
+    Bennett,
+    /// The same formula scaled to the pressure and temperature at the
+    /// station, from a sensor or else a standard atmosphere at its height.
+    Meteorological,
+}
+
+/// Surface meteorological conditions at the station.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Weather {
+    /// Pressure (hPa).
+    pub pressure: f64,
+    /// Temperature (°C).
+    pub temperature: f64,
+}
+
+impl Weather {
+    /// Measured conditions given as `PRESSURE,TEMPERATURE`, in hPa and °C.
+    pub fn from_arg(arg: &str) -> Option<Self> {
+        let (pressure, temperature) = arg.split_once(',')?;
+        let weather = Weather {
+            pressure: pressure.trim().parse().ok()?,
+            temperature: temperature.trim().parse().ok()?,
+        };
+        (weather.pressure > 0.0 && weather.temperature > -273.15).then_some(weather)
+    }
+
+    /// ICAO standard atmosphere at an ellipsoidal height (m), taken as the
+    /// height above sea level.
+    pub fn standard_atmosphere(height: f64) -> Self {
+        Weather {
+            pressure: 1013.25 * (1.0 - 2.255_77e-5 * height).powf(5.255_88),
+            temperature: 15.0 - 0.0065 * height,
+        }
+    }
+
+    /// Measured conditions where the station has them, a standard atmosphere
+    /// at its height otherwise.
+    pub fn at_station(station: &StationMetadata) -> Self {
+        if let Some(weather) = station.weather {
+            return weather;
+        }
+        let height = station.position.map_or(0.0, |p| p.orthometric_height.unwrap_or(p.ellipsoidal_height));
+        Weather::standard_atmosphere(height)
+    }
+}
+
+/// Bending (degrees) of a ray arriving at geometric elevation `elevation`
+/// (degrees), by Saemundsson's form of Bennett's formula for true rather
+/// than apparent elevations.
+fn bennett(elevation: f64) -> f64 {
+    let arcminutes = 1.02 / (elevation + 10.3 / (elevation + 5.11)).to_radians().tan();
+    arcminutes / 60.0
+}
+
+impl RefractionModel {
+    pub fn from_name(name: &str) -> Option<Self> {
+        let model = match name.to_ascii_lowercase().as_str() {
+            "none" => RefractionModel::None,
+            "bennett" => RefractionModel::Bennett,
+            "meteorological" | "met" => RefractionModel::Meteorological,
+            _ => return None,
+        };
+        Some(model)
+    }
+
+    /// Apparent elevation (degrees) of a satellite at geometric elevation
+    /// `elevation`, i.e. the angle the reflected signal actually arrives at.
+    pub fn apparent_elevation(&self, elevation: f64, weather: &Weather) -> f64 {
+        if !(MIN_ELEVATION..90.0).contains(&elevation) {
+            return elevation;
+        }
+        match self {
+            RefractionModel::None => elevation,
+            RefractionModel::Bennett => elevation + bennett(elevation),
+            RefractionModel::Meteorological => {
+                let scale = (weather.pressure / STANDARD_PRESSURE) * ((273.15 + STANDARD_TEMPERATURE) / (273.15 + weather.temperature));
+                elevation + scale * bennett(elevation)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::station::StationPosition;
+
+    #[test]
+    fn bennett_at_five_degrees() {
+        // 1.02 / tan(5° + 10.3° / 10.11) = 9.674 arcminutes.
+        let weather = Weather { pressure: STANDARD_PRESSURE, temperature: STANDARD_TEMPERATURE };
+        let apparent = RefractionModel::Bennett.apparent_elevation(5.0, &weather);
+        assert!((apparent - 5.161_235).abs() < 1e-6, "{}", apparent);
+        // Under the formula's own conditions both models agree.
+        let met = RefractionModel::Meteorological.apparent_elevation(5.0, &weather);
+        assert!((met - apparent).abs() < 1e-12);
+        assert_eq!(RefractionModel::None.apparent_elevation(5.0, &weather), 5.0);
+    }
+
+    #[test]
+    fn measured_weather_changes_the_correction() {
+        let mut station = StationMetadata {
+            position: Some(StationPosition::from_ecef(3_924_687.701, 301_132.765, 5_001_910.833)),
+            ..StationMetadata::default()
+        };
+        let standard = Weather::at_station(&station);
+        let model = RefractionModel::Meteorological;
+        let before = model.apparent_elevation(5.0, &standard);
+
+        // A cold, high pressure day bends the signal more.
+        station.weather = Weather::from_arg("1030,-10");
+        let measured = Weather::at_station(&station);
+        assert_eq!(measured, Weather { pressure: 1030.0, temperature: -10.0 });
+        let after = model.apparent_elevation(5.0, &measured);
+        assert!((after - 5.176_925).abs() < 1e-6, "{}", after);
+        assert!(after - before > 0.01);
+    }
+
+    #[test]
+    fn weather_argument() {
+        assert_eq!(Weather::from_arg("1013.2, 21.5"), Some(Weather { pressure: 1013.2, temperature: 21.5 }));
+        assert_eq!(Weather::from_arg("1013.2"), None);
+        assert_eq!(Weather::from_arg("-5,10"), None);
+    }
+}
@@ -0,0 +1,721 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read};
+
+use crate::config::Config;
+use crate::db::record::{Band, Network, Quality, Record};
+use crate::station::{StationMetadata, StationPosition};
+use crate::source::StreamSource;
+use crate::stream::RecordParser;
+use crate::time::{days_from_civil, Epoch};
+
+pub mod nav;
+pub mod obs2;
+pub mod obs3;
+
+/// BeiDou time runs 14 s behind GPS time.
+const BDT_OFFSET_MILLIS: i64 = 14_000;
+
+/// Longest line kept while waiting for its newline. RINEX 3 observation lines
+/// grow with the number of observables, 16 columns each.
+const MAX_LINE_LENGTH: usize = 4096;
+
+/// Reasons a stream cannot be read as RINEX observations at all.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RinexError {
+    NotRinex,
+    NotObservationFile,
+    NotNavigationFile,
+    UnsupportedVersion(f64),
+    MalformedHeader(&'static str),
+    MissingObservationTypes,
+    TruncatedHeader,
+}
+
+impl fmt::Display for RinexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RinexError::NotRinex => write!(f, "not a RINEX file"),
+            RinexError::NotObservationFile => write!(f, "not a RINEX observation file"),
+            RinexError::NotNavigationFile => write!(f, "not a RINEX navigation file"),
+            RinexError::UnsupportedVersion(v) => write!(f, "unsupported RINEX version {:.2}", v),
+            RinexError::MalformedHeader(label) => write!(f, "malformed RINEX header record '{}'", label),
+            RinexError::MissingObservationTypes => write!(f, "RINEX header lists no observation types"),
+            RinexError::TruncatedHeader => write!(f, "RINEX header ends before END OF HEADER"),
+        }
+    }
+}
+
+impl std::error::Error for RinexError {}
+
+/// Counts of how the body of an observation file was handled.
+#[derive(Debug, Clone, Default)]
+pub struct RinexReport {
+    pub epochs: usize,
+    pub event_epochs: usize,
+    pub malformed_lines: usize,
+    pub unknown_satellites: usize,
+    pub excluded_satellites: usize,
+}
+
+impl fmt::Display for RinexReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} epochs, {} event records skipped, {} malformed lines, {} unknown and {} excluded satellite observations",
+            self.epochs,
+            self.event_epochs,
+            self.malformed_lines,
+            self.unknown_satellites,
+            self.excluded_satellites,
+        )
+    }
+}
+
+/// Time scale the epochs of a file are given in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeSystem {
+    /// GPS time; Galileo, QZSS and NavIC time are kept aligned to it.
+    #[default]
+    Gps,
+    BeiDou,
+    /// GLONASS files are in UTC(SU).
+    Utc,
+}
+
+impl TimeSystem {
+    pub(crate) fn from_code(code: &str) -> Option<Self> {
+        let system = match code {
+            "GPS" | "GAL" | "QZS" | "IRN" => TimeSystem::Gps,
+            "BDT" => TimeSystem::BeiDou,
+            "GLO" | "UTC" => TimeSystem::Utc,
+            _ => return None,
+        };
+        Some(system)
+    }
+
+    /// Time scale of a single-system file without an explicit time system.
+    fn of_file_system(system: char) -> Self {
+        match system {
+            'R' => TimeSystem::Utc,
+            'C' => TimeSystem::BeiDou,
+            _ => TimeSystem::Gps,
+        }
+    }
+
+    /// Converts a calendar epoch on this time scale, rounded to milliseconds.
+    pub fn epoch(&self, year: i32, month: u32, day: u32, hour: u32, minute: u32, seconds: f64) -> Epoch {
+        let days = days_from_civil(year, month, day);
+        let millis = (hour as i64 * 3600 + minute as i64 * 60) * 1000 + (seconds * 1000.0).round() as i64;
+        match self {
+            TimeSystem::Gps => Epoch::from_gps_days(days, millis),
+            TimeSystem::BeiDou => Epoch::from_gps_days(days, millis + BDT_OFFSET_MILLIS),
+            TimeSystem::Utc => Epoch::from_utc_days(days, millis),
+        }
+    }
+}
+
+/// The parts of an observation file header the pipeline uses.
+#[derive(Debug, Clone, Default)]
+pub struct RinexHeader {
+    pub version: f64,
+    /// Satellite system of the file: G, R, E, C, J, I, S or M for mixed.
+    pub system: char,
+    pub marker_name: Option<String>,
+    /// Approximate marker position, WGS84 ECEF (m).
+    pub approx_position: Option<(f64, f64, f64)>,
+    /// Antenna reference point above the marker, height/east/north (m).
+    pub antenna_delta: Option<(f64, f64, f64)>,
+    pub interval: Option<f64>,
+    pub time_system: Option<TimeSystem>,
+    /// Observation codes per satellite system in file order, e.g. `S1C`.
+    /// RINEX 2 has one list (`S1`, ...) for all systems, kept under `' '`.
+    pub obs_types: HashMap<char, Vec<String>>,
+    /// System whose observation type list continues on the next line.
+    continued_system: Option<char>,
+}
+
+impl RinexHeader {
+    /// Adds one header line. Returns true at `END OF HEADER`.
+    pub(crate) fn push_line(&mut self, line: &str) -> Result<bool, RinexError> {
+        let label = column(line, 60, 80);
+
+        if self.version == 0.0 {
+            if label != "RINEX VERSION / TYPE" {
+                return Err(RinexError::NotRinex);
+            }
+            self.version = column(line, 0, 9).parse().map_err(|_| RinexError::MalformedHeader("RINEX VERSION / TYPE"))?;
+            if column(line, 20, 21) != "O" {
+                return Err(RinexError::NotObservationFile);
+            }
+            if !(2.0..4.0).contains(&self.version) {
+                return Err(RinexError::UnsupportedVersion(self.version));
+            }
+            self.system = column(line, 40, 41).chars().next().unwrap_or('G');
+            return Ok(false);
+        }
+
+        match label {
+            "MARKER NAME" => {
+                let name = column(line, 0, 60);
+                self.marker_name = (!name.is_empty()).then(|| name.to_string());
+            }
+            "APPROX POSITION XYZ" => {
+                self.approx_position = Some(parse_triple(line).ok_or(RinexError::MalformedHeader("APPROX POSITION XYZ"))?);
+            }
+            "ANTENNA: DELTA H/E/N" => {
+                self.antenna_delta = Some(parse_triple(line).ok_or(RinexError::MalformedHeader("ANTENNA: DELTA H/E/N"))?);
+            }
+            "INTERVAL" => {
+                self.interval = Some(column(line, 0, 10).parse().map_err(|_| RinexError::MalformedHeader("INTERVAL"))?);
+            }
+            "TIME OF FIRST OBS" => {
+                self.time_system = TimeSystem::from_code(column(line, 48, 51));
+            }
+            "SYS / # / OBS TYPES" => self.push_obs_types(line)?,
+            "# / TYPES OF OBSERV" => {
+                let codes = self.obs_types.entry(' ').or_default();
+                for i in 0..9 {
+                    let code = column(line, 10 + 6 * i, 12 + 6 * i);
+                    if !code.is_empty() {
+                        codes.push(code.to_string());
+                    }
+                }
+            }
+            "END OF HEADER" => {
+                if self.obs_types.values().all(|codes| codes.is_empty()) {
+                    return Err(RinexError::MissingObservationTypes);
+                }
+                return Ok(true);
+            }
+            _ => {}
+        }
+        Ok(false)
+    }
+
+    fn push_obs_types(&mut self, line: &str) -> Result<(), RinexError> {
+        let system = match column(line, 0, 1).chars().next() {
+            Some(system) => {
+                self.continued_system = Some(system);
+                system
+            }
+            None => self.continued_system.ok_or(RinexError::MalformedHeader("SYS / # / OBS TYPES"))?,
+        };
+
+        let codes = self.obs_types.entry(system).or_default();
+        for i in 0..13 {
+            let code = column(line, 7 + 4 * i, 10 + 4 * i);
+            if !code.is_empty() {
+                codes.push(code.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Observation codes of a satellite system, in the order of the values.
+    pub fn observation_codes(&self, system: char) -> Option<&[String]> {
+        let key = if self.version < 3.0 { ' ' } else { system };
+        self.obs_types.get(&key).map(|codes| codes.as_slice())
+    }
+
+    /// Time scale of the epochs in the body.
+    pub fn epoch_time_system(&self) -> TimeSystem {
+        self.time_system.unwrap_or_else(|| TimeSystem::of_file_system(self.system))
+    }
+
+    pub fn station_metadata(&self) -> StationMetadata {
+        StationMetadata {
+            marker_name: self.marker_name.clone(),
+            // A zero position is how RINEX writers say "unknown".
+            position: self.approx_position
+                .filter(|&(x, y, z)| x != 0.0 || y != 0.0 || z != 0.0)
+                .map(|(x, y, z)| StationPosition::from_ecef(x, y, z)),
+            antenna_height: self.antenna_delta.map(|(h, _, _)| h),
+            interval: self.interval,
+            ..StationMetadata::default()
+        }
+    }
+}
+
+enum State {
+    Header,
+    /// Waiting for the next epoch line.
+    Epochs,
+    /// RINEX 3: satellite lines of the current epoch still to come.
+    Observations { time: Epoch, remaining: usize },
+    /// RINEX 2: the satellite list of the epoch line continues.
+    SatelliteList { time: Epoch, count: usize, satellites: Vec<(char, u32)> },
+    /// RINEX 2: observation lines of the epoch, satellite after satellite,
+    /// with the values read so far for the current one.
+    SatelliteObservations { time: Epoch, satellites: Vec<(char, u32)>, index: usize, values: Vec<Option<f64>> },
+    /// Lines of an event record still to skip.
+    Skip { remaining: usize },
+    Failed(RinexError),
+}
+
+/// Incremental RINEX observation parser. Only the signal strength (`S`)
+/// observables are kept. RINEX files carry no satellite geometry, so the
+/// records have NaN elevation and azimuth until they are computed from orbits.
+pub struct RinexParser {
+    config: Config,
+    buffer: Vec<u8>,
+    header: RinexHeader,
+    state: State,
+    report: RinexReport,
+}
+
+impl RinexParser {
+    pub fn new(config: &Config) -> Self {
+        RinexParser {
+            config: config.clone(),
+            buffer: Vec::with_capacity(MAX_LINE_LENGTH),
+            header: RinexHeader::default(),
+            state: State::Header,
+            report: RinexReport::default(),
+        }
+    }
+
+    pub fn report(&self) -> &RinexReport {
+        &self.report
+    }
+
+    pub fn header(&self) -> &RinexHeader {
+        &self.header
+    }
+
+    /// Why the stream could not be read, if it could not.
+    pub fn error(&self) -> Option<&RinexError> {
+        match &self.state {
+            State::Failed(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    fn push_line(&mut self, line: &str, records: &mut Vec<Record>) {
+        let line = line.trim_end();
+        let state = std::mem::replace(&mut self.state, State::Epochs);
+        self.state = match state {
+            State::Failed(e) => State::Failed(e),
+            State::Header => match self.header.push_line(line) {
+                Ok(true) => State::Epochs,
+                Ok(false) => State::Header,
+                Err(e) => State::Failed(e),
+            },
+            // A new epoch line in the middle of an epoch means the previous one was cut short.
+            State::Epochs | State::Observations { .. } if self.header.version >= 3.0 && line.starts_with('>') => {
+                self.push_epoch_line(line)
+            }
+            State::Epochs if self.header.version < 3.0 => self.push_epoch_line(line),
+            State::Epochs => {
+                if !line.is_empty() {
+                    self.report.malformed_lines += 1;
+                }
+                State::Epochs
+            }
+            State::Observations { time, remaining } => {
+                self.push_satellite_line(line, time, records);
+                if remaining > 1 { State::Observations { time, remaining: remaining - 1 } } else { State::Epochs }
+            }
+            State::SatelliteList { time, count, mut satellites } => {
+                if obs2::parse_satellite_list(line, count, &mut satellites).is_none() {
+                    self.report.malformed_lines += 1;
+                    return;
+                }
+                self.observe_satellites(time, count, satellites)
+            }
+            State::SatelliteObservations { time, satellites, index, values } => {
+                self.push_observation_line(line, time, satellites, index, values, records)
+            }
+            State::Skip { remaining } => {
+                if remaining > 1 { State::Skip { remaining: remaining - 1 } } else { State::Epochs }
+            }
+        };
+    }
+
+    /// Parses an epoch line and returns what to expect next.
+    fn push_epoch_line(&mut self, line: &str) -> State {
+        let time_system = self.header.epoch_time_system();
+        let parsed = if self.header.version < 3.0 {
+            obs2::parse_epoch_line(line, time_system).map(|e| (e.time, e.flag, e.count, e.satellites))
+        } else {
+            obs3::parse_epoch_line(line, time_system).map(|e| (e.time, e.flag, e.count, Vec::new()))
+        };
+        let (time, flag, count, satellites) = match parsed {
+            Some(parsed) => parsed,
+            None => {
+                self.report.malformed_lines += 1;
+                return State::Epochs;
+            }
+        };
+
+        match (flag, time) {
+            // 0: OK, 1: power failure since the previous epoch; both carry observations.
+            (0 | 1, Some(time)) if count > 0 => {
+                self.report.epochs += 1;
+                if self.header.version >= 3.0 {
+                    State::Observations { time, remaining: count }
+                } else {
+                    self.observe_satellites(time, count, satellites)
+                }
+            }
+            (0 | 1, _) => State::Epochs,
+            // Events and cycle slip records, followed by `count` lines we have no use for.
+            _ => {
+                self.report.event_epochs += 1;
+                if count > 0 { State::Skip { remaining: count } } else { State::Epochs }
+            }
+        }
+    }
+
+    /// RINEX 2: continues with the satellite list until all `count`
+    /// satellites are known, then with their observations.
+    fn observe_satellites(&self, time: Epoch, count: usize, satellites: Vec<(char, u32)>) -> State {
+        if satellites.len() < count {
+            return State::SatelliteList { time, count, satellites };
+        }
+        let n_codes = self.header.observation_codes(' ').map_or(0, |codes| codes.len());
+        State::SatelliteObservations { time, satellites, index: 0, values: Vec::with_capacity(n_codes) }
+    }
+
+    /// RINEX 2: adds one observation line of the current satellite, which may
+    /// need several lines for all its observation types.
+    fn push_observation_line(
+        &mut self,
+        line: &str,
+        time: Epoch,
+        satellites: Vec<(char, u32)>,
+        mut index: usize,
+        mut values: Vec<Option<f64>>,
+        records: &mut Vec<Record>,
+    ) -> State {
+        let n_codes = self.header.observation_codes(' ').map_or(0, |codes| codes.len());
+        if obs2::parse_observation_line(line, n_codes - values.len(), &mut values).is_none() {
+            // Drop the satellite but stay aligned with the line count.
+            self.report.malformed_lines += 1;
+            values.resize(values.len() + (n_codes - values.len()).min(obs2::OBSERVATIONS_PER_LINE), None);
+        }
+        if values.len() < n_codes {
+            return State::SatelliteObservations { time, satellites, index, values };
+        }
+
+        let (system, prn) = satellites[index];
+        self.push_satellite_values(time, system, prn, &values, records);
+        index += 1;
+        values.clear();
+        if index < satellites.len() {
+            State::SatelliteObservations { time, satellites, index, values }
+        } else {
+            State::Epochs
+        }
+    }
+
+    /// RINEX 3: one line holds all observations of a satellite.
+    fn push_satellite_line(&mut self, line: &str, time: Epoch, records: &mut Vec<Record>) {
+        match obs3::parse_satellite_line(line) {
+            Some((system, prn, values)) => self.push_satellite_values(time, system, prn, &values, records),
+            None => self.report.malformed_lines += 1,
+        }
+    }
+
+    fn push_satellite_values(&mut self, time: Epoch, system: char, prn: u32, values: &[Option<f64>], records: &mut Vec<Record>) {
+
+        let (network, satellite) = match normalise_satellite(system, prn) {
+            Some(sat) => sat,
+            None => {
+                self.report.unknown_satellites += 1;
+                return;
+            }
+        };
+        if !self.config.networks.contains(&network) {
+            self.report.excluded_satellites += 1;
+            return;
+        }
+
+        let codes = match self.header.observation_codes(system) {
+            Some(codes) => codes,
+            None => {
+                self.report.malformed_lines += 1;
+                return;
+            }
+        };
+        let observations = codes.iter().zip(values)
+            .filter_map(|(code, value)| value.map(|v| (code.as_str(), v)));
+        snr_records_into(time, network, satellite, observations, self.header.version, records);
+    }
+}
+
+impl RecordParser for RinexParser {
+    fn push_bytes(&mut self, chunk: &[u8], records: &mut Vec<Record>) {
+        for &byte in chunk {
+            if byte == b'\n' {
+                let line = std::mem::take(&mut self.buffer);
+                self.push_line(&String::from_utf8_lossy(&line), records);
+                self.buffer = line;
+                self.buffer.clear();
+            } else if self.buffer.len() < MAX_LINE_LENGTH {
+                self.buffer.push(byte);
+            }
+        }
+    }
+
+    fn finish(&mut self, records: &mut Vec<Record>) {
+        let line = std::mem::take(&mut self.buffer);
+        if !line.is_empty() {
+            self.push_line(&String::from_utf8_lossy(&line), records);
+        }
+        if matches!(self.state, State::Header) {
+            self.state = State::Failed(RinexError::TruncatedHeader);
+        }
+    }
+
+    fn station(&self) -> StationMetadata {
+        self.header.station_metadata()
+    }
+
+    fn summary(&self) -> String {
+        format!("RINEX {:.2}: {}", self.header.version, self.report)
+    }
+
+    fn failure(&self) -> Option<String> {
+        self.error().map(|e| e.to_string())
+    }
+}
+
+/// Everything extracted from a complete observation file.
+pub struct RinexLog {
+    pub records: Vec<Record>,
+    pub report: RinexReport,
+    pub header: RinexHeader,
+    pub station: StationMetadata,
+}
+
+/// Parses a complete RINEX observation stream, e.g. a daily file.
+pub fn read_records<R: Read>(reader: R, config: &Config) -> io::Result<RinexLog> {
+    let mut source = StreamSource::new(reader, RinexParser::new(config));
+    let records = source.read_all();
+    let parser = source.parser();
+    if let Some(e) = parser.error() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, e.clone()));
+    }
+    let records = records?;
+    Ok(RinexLog {
+        records,
+        report: parser.report().clone(),
+        header: parser.header().clone(),
+        station: parser.header().station_metadata(),
+    })
+}
+
+/// Turns the signal strength observables of one satellite in one epoch into
+/// records, one per band. When a band carries several tracking modes (e.g.
+/// S2L and S2W) the first one listed in the header wins.
+fn snr_records_into<'a>(
+    time: Epoch,
+    network: Network,
+    satellite: u32,
+    observations: impl Iterator<Item = (&'a str, f64)>,
+    version: f64,
+    records: &mut Vec<Record>,
+) {
+    let mut bands = Vec::new();
+    for (code, snr) in observations {
+        if !code.starts_with('S') || snr <= 0.0 {
+            continue;
+        }
+        let band = band_from_code(network, code, version);
+        if band == Band::Unknown || bands.contains(&band) {
+            continue;
+        }
+        bands.push(band);
+        records.push(Record {
+            id: Record::make_id(network, band, satellite),
+            satellite,
+            elevation: f64::NAN,
+            azimuth: f64::NAN,
+            snr,
+            time,
+            network,
+            band,
+            quality: Quality::default(),
+        });
+    }
+}
+
+pub fn system_to_network(system: char) -> Option<Network> {
+    let network = match system {
+        'G' => Network::GPS,
+        'R' => Network::GLONASS,
+        'E' => Network::Galileo,
+        'C' => Network::BeiDou,
+        'J' => Network::QZSS,
+        'I' => Network::NavIC,
+        'S' => Network::SBAS,
+        _ => return None,
+    };
+    Some(network)
+}
+
+/// Network and satellite number of a RINEX satellite id, numbered as in the
+/// other inputs (SBAS by PRN, 120-158).
+pub(crate) fn normalise_satellite(system: char, prn: u32) -> Option<(Network, u32)> {
+    let network = system_to_network(system)?;
+    let satellite = match network {
+        Network::SBAS => prn + 100,
+        _ => prn,
+    };
+    Some((network, satellite))
+}
+
+/// Maps a RINEX observation code (`S1C`, or `S1` in RINEX 2) to the carrier
+/// it was observed on. The band digit means a different frequency for every
+/// constellation.
+pub fn band_from_code(network: Network, code: &str, version: f64) -> Band {
+    let (digit, attribute) = match code.as_bytes() {
+        [_, digit] => (*digit, None),
+        [_, digit, attribute, ..] => (*digit, Some(*attribute)),
+        _ => return Band::Unknown,
+    };
+    match (network, digit) {
+        (Network::GPS | Network::QZSS | Network::SBAS, b'1') => Band::L1,
+        (Network::GPS | Network::QZSS, b'2') => Band::L2,
+        (Network::GPS | Network::QZSS | Network::SBAS | Network::NavIC, b'5') => Band::L5,
+        (Network::QZSS, b'6') => Band::L6,
+        (Network::NavIC, b'9') => Band::S,
+        (Network::GLONASS, b'1') => Band::G1,
+        (Network::GLONASS, b'2') => Band::G2,
+        (Network::Galileo, b'1') => Band::E1,
+        (Network::Galileo, b'5') => Band::E5a,
+        (Network::Galileo, b'7') => Band::E5b,
+        (Network::Galileo, b'8') => Band::E5,
+        (Network::Galileo, b'6') => Band::E6,
+        // Up to RINEX 3.02, B1I was listed as band 1; later versions moved it to band 2.
+        (Network::BeiDou, b'1') if version < 3.03 && matches!(attribute, Some(b'I' | b'Q' | b'X')) => Band::B1I,
+        (Network::BeiDou, b'1') => Band::B1C,
+        (Network::BeiDou, b'2') => Band::B1I,
+        (Network::BeiDou, b'5') => Band::B2a,
+        (Network::BeiDou, b'7') => Band::B2b,
+        (Network::BeiDou, b'8') => Band::B2,
+        (Network::BeiDou, b'6') => Band::B3,
+        _ => Band::Unknown,
+    }
+}
+
+/// Trimmed text of the columns `start..end` of a fixed-width line, empty
+/// where the line is shorter.
+pub(crate) fn column(line: &str, start: usize, end: usize) -> &str {
+    line.get(start.min(line.len())..end.min(line.len())).unwrap_or("").trim()
+}
+
+/// Three F14.4 values, as in the position and antenna offset records.
+fn parse_triple(line: &str) -> Option<(f64, f64, f64)> {
+    let value = |i: usize| column(line, 14 * i, 14 * (i + 1)).parse::<f64>().ok();
+    Some((value(0)?, value(1)?, value(2)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header_line(content: &str, label: &str) -> String {
+        format!("{:<60}{}\n", content, label)
+    }
+
+    /// F14.3 observations with blank indicators; `None` is a blank field.
+    fn observations(values: &[Option<f64>]) -> String {
+        values.iter().map(|v| v.map_or(" ".repeat(16), |v| format!("{:14.3}  ", v))).collect()
+    }
+
+    fn parse(text: &str) -> RinexLog {
+        read_records(text.as_bytes(), &Config::default()).unwrap()
+    }
+
+    #[test]
+    fn rinex3_observations() {
+        let mut text = String::new();
+        text += &header_line(&format!("{:>9}{:11}{:<20}{:<20}", "3.04", "", "OBSERVATION DATA", "M"), "RINEX VERSION / TYPE");
+        text += &header_line("REFL", "MARKER NAME");
+        text += &header_line(&format!("{:14.4}{:14.4}{:14.4}", 3_924_687.701, 301_132.765, 5_001_910.833), "APPROX POSITION XYZ");
+        text += &header_line(&format!("{:14.4}{:14.4}{:14.4}", 1.5, 0.0, 0.0), "ANTENNA: DELTA H/E/N");
+        text += &header_line("G    3 C1C S1C S2W", "SYS / # / OBS TYPES");
+        text += &header_line("E    2 C1C S1C", "SYS / # / OBS TYPES");
+        text += &header_line(&format!("{:>6}{:>6}{:>6}{:>6}{:>6}{:>13.7}{:>5}{:>3}", 2024, 1, 1, 0, 0, 0.0, "", "GPS"), "TIME OF FIRST OBS");
+        text += &header_line("", "END OF HEADER");
+        // An event record with one comment line, then an epoch of two satellites.
+        text += &format!("> 2024 01 01 00 00{:>11.7}  4{:>3}\n", 15.0, 1);
+        text += &header_line("ANTENNA MOVED?", "COMMENT");
+        text += &format!("> 2024 01 01 00 00{:>11.7}  0{:>3}\n", 30.0, 2);
+        text += &format!("G05{}\n", observations(&[Some(20_123_456.789), Some(45.25), Some(38.5)]));
+        text += &format!("E11{}\n", observations(&[None, Some(41.0)]));
+
+        let log = parse(&text);
+        assert_eq!((log.report.epochs, log.report.event_epochs, log.report.malformed_lines), (1, 1, 0));
+        // 2024-01-01 is the Monday of GPS week 2295.
+        let time = Epoch::from_gps_week_millis(2295, 86_400_000 + 30_000);
+        let records: Vec<_> = log.records.iter().map(|r| (r.network, r.satellite, r.band, r.snr, r.time)).collect();
+        assert_eq!(records, vec![
+            (Network::GPS, 5, Band::L1, 45.25, time),
+            (Network::GPS, 5, Band::L2, 38.5, time),
+            (Network::Galileo, 11, Band::E1, 41.0, time),
+        ]);
+        assert!(log.records.iter().all(|r| r.elevation.is_nan() && r.azimuth.is_nan()));
+
+        assert_eq!(log.station.marker_name.as_deref(), Some("REFL"));
+        assert_eq!(log.station.antenna_height, Some(1.5));
+        let (x, y, z) = log.station.position.unwrap().ecef();
+        assert!((x - 3_924_687.701).abs() < 1e-3 && (y - 301_132.765).abs() < 1e-3 && (z - 5_001_910.833).abs() < 1e-3);
+    }
+
+    #[test]
+    fn rinex2_observations() {
+        let mut text = String::new();
+        text += &header_line(&format!("{:>9}{:11}{:<20}{:<20}", "2.11", "", "OBSERVATION DATA", "M (MIXED)"), "RINEX VERSION / TYPE");
+        text += &header_line(&format!("{:>6}{:>6}{:>6}{:>6}{:>6}{:>6}{:>6}", 6, "C1", "L1", "L2", "P2", "S1", "S2"), "# / TYPES OF OBSERV");
+        text += &header_line("", "END OF HEADER");
+        // Six observation types take two lines per satellite; a blank system means GPS.
+        text += &format!(" 24  1  1  0  0{:>11.7}  0{:>3} 05R07\n", 30.0, 2);
+        text += &format!("{}\n", observations(&[Some(20_123_456.789), Some(1.0), Some(2.0), Some(20_123_460.0), Some(44.0)]));
+        text += &format!("{}\n", observations(&[Some(30.5)]));
+        text += &format!("{}\n", observations(&[Some(21_000_000.0), Some(1.0), None, None, Some(40.0)]));
+        text += "\n";
+
+        let log = parse(&text);
+        assert_eq!((log.report.epochs, log.report.malformed_lines), (1, 0));
+        let time = Epoch::from_gps_week_millis(2295, 86_400_000 + 30_000);
+        let records: Vec<_> = log.records.iter().map(|r| (r.network, r.satellite, r.band, r.snr, r.time)).collect();
+        assert_eq!(records, vec![
+            (Network::GPS, 5, Band::L1, 44.0, time),
+            (Network::GPS, 5, Band::L2, 30.5, time),
+            (Network::GLONASS, 7, Band::G1, 40.0, time),
+        ]);
+        assert!(log.station.position.is_none());
+    }
+
+    #[test]
+    fn rinex2_corrupted_satellite_list() {
+        let mut text = String::new();
+        text += &header_line(&format!("{:>9}{:11}{:<20}{:<20}", "2.11", "", "OBSERVATION DATA", "M (MIXED)"), "RINEX VERSION / TYPE");
+        text += &header_line(&format!("{:>6}{:>6}", 1, "S1"), "# / TYPES OF OBSERV");
+        text += &header_line("", "END OF HEADER");
+        // A byte lost on a noisy line, as decoded into U+FFFD.
+        text += &format!(" 24  1  1  0  0{:>11.7}  0{:>3} 05\u{FFFD}07\n", 30.0, 2);
+        text += &format!("{}\n", observations(&[Some(44.0)]));
+        text += &format!("{}\n", observations(&[Some(40.0)]));
+        text += &format!(" 24  1  1  0  0{:>11.7}  0{:>3}G05\n", 31.0, 1);
+        text += &format!("{}\n", observations(&[Some(45.0)]));
+
+        let log = parse(&text);
+        assert_eq!(log.report.epochs, 1);
+        assert!(log.report.malformed_lines > 0);
+        let records: Vec<_> = log.records.iter().map(|r| (r.network, r.satellite, r.snr)).collect();
+        assert_eq!(records, vec![(Network::GPS, 5, 45.0)]);
+    }
+
+    #[test]
+    fn not_an_observation_file() {
+        let text = header_line(&format!("{:>9}{:11}{:<20}{:<20}", "3.04", "", "N: GNSS NAV DATA", "M"), "RINEX VERSION / TYPE");
+        let Err(error) = read_records(text.as_bytes(), &Config::default()) else {
+            panic!("a navigation file was read as observations");
+        };
+        assert_eq!(error.to_string(), RinexError::NotObservationFile.to_string());
+    }
+}
@@ -0,0 +1,242 @@
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read};
+
+use crate::db::record::Network;
+use crate::orbit::BroadcastOrbits;
+use crate::orbit::glonass::GlonassEphemeris;
+use crate::orbit::kepler::KeplerEphemeris;
+use crate::time::Epoch;
+use super::{column, system_to_network, RinexError, TimeSystem};
+
+/// Width of one D19.12 value, and where the values of the first line and of
+/// the broadcast orbit lines start.
+const VALUE_WIDTH: usize = 19;
+const FIRST_LINE_START: usize = 23;
+const ORBIT_LINE_START: usize = 4;
+
+/// BeiDou weeks count from 2006-01-01, 1356 GPS weeks later.
+const BEIDOU_WEEK_OFFSET: u32 = 1356;
+
+/// Counts of how the records of a navigation file were handled.
+#[derive(Debug, Clone, Default)]
+pub struct NavReport {
+    pub ephemerides: usize,
+    pub unhealthy: usize,
+    /// SBAS and unknown records, which carry no usable orbit here.
+    pub skipped: usize,
+    pub malformed: usize,
+}
+
+impl fmt::Display for NavReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ephemerides, {} unhealthy, {} skipped, {} malformed",
+            self.ephemerides, self.unhealthy, self.skipped, self.malformed,
+        )
+    }
+}
+
+pub struct NavLog {
+    pub orbits: BroadcastOrbits,
+    pub report: NavReport,
+    pub version: f64,
+}
+
+/// Number of broadcast orbit lines after the first line of a record.
+fn orbit_lines(system: char, version: f64) -> Option<usize> {
+    match system {
+        'G' | 'E' | 'C' | 'J' | 'I' => Some(7),
+        // RINEX 3.05 added a fourth GLONASS orbit line.
+        'R' if version >= 3.05 => Some(4),
+        'R' | 'S' => Some(3),
+        _ => None,
+    }
+}
+
+/// One value of a record line, with Fortran `D` exponents. Blank fields are zero.
+fn value(line: &str, start: usize) -> Option<f64> {
+    let field = column(line, start, start + VALUE_WIDTH);
+    if field.is_empty() {
+        return Some(0.0);
+    }
+    field.replace(['D', 'd'], "E").parse().ok()
+}
+
+/// The values of a record: three on the first line, four on every orbit line.
+fn record_values(lines: &[String]) -> Option<Vec<f64>> {
+    let mut values = Vec::with_capacity(3 + 4 * (lines.len() - 1));
+    for i in 0..3 {
+        values.push(value(&lines[0], FIRST_LINE_START + VALUE_WIDTH * i)?);
+    }
+    for line in &lines[1..] {
+        for i in 0..4 {
+            values.push(value(line, ORBIT_LINE_START + VALUE_WIDTH * i)?);
+        }
+    }
+    Some(values)
+}
+
+/// The epoch of the first line, `Snn yyyy mm dd hh mm ss`.
+fn record_epoch(line: &str, time_system: TimeSystem) -> Option<Epoch> {
+    let year = column(line, 4, 8).parse::<i32>().ok()?;
+    let month = column(line, 9, 11).parse::<u32>().ok()?;
+    let day = column(line, 12, 14).parse::<u32>().ok()?;
+    let hour = column(line, 15, 17).parse::<u32>().ok()?;
+    let minute = column(line, 18, 20).parse::<u32>().ok()?;
+    let second = column(line, 21, 23).parse::<u32>().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    Some(time_system.epoch(year, month, day, hour, minute, second as f64))
+}
+
+/// Keplerian elements from the values of a GPS, Galileo, BeiDou, QZSS or NavIC record.
+fn kepler_ephemeris(network: Network, satellite: u32, v: &[f64]) -> KeplerEphemeris {
+    let toe_seconds = v[11];
+    let week = v[21] as u32;
+    let toe = match network {
+        Network::BeiDou => Epoch::from_gps_week_millis(week + BEIDOU_WEEK_OFFSET, (toe_seconds * 1000.0).round() as i64 + 14_000),
+        _ => Epoch::from_gps_week_millis(week, (toe_seconds * 1000.0).round() as i64),
+    };
+    KeplerEphemeris {
+        network,
+        satellite,
+        toe,
+        toe_seconds,
+        crs: v[4],
+        delta_n: v[5],
+        m0: v[6],
+        cuc: v[7],
+        e: v[8],
+        cus: v[9],
+        sqrt_a: v[10],
+        cic: v[12],
+        omega0: v[13],
+        cis: v[14],
+        i0: v[15],
+        crc: v[16],
+        omega: v[17],
+        omega_dot: v[18],
+        idot: v[19],
+        healthy: v[24] == 0.0,
+    }
+}
+
+/// State vector from the values of a GLONASS record, converted from km to m.
+fn glonass_ephemeris(satellite: u32, tb: Epoch, v: &[f64]) -> GlonassEphemeris {
+    GlonassEphemeris {
+        satellite,
+        tb,
+        position: [v[3] * 1e3, v[7] * 1e3, v[11] * 1e3],
+        velocity: [v[4] * 1e3, v[8] * 1e3, v[12] * 1e3],
+        acceleration: [v[5] * 1e3, v[9] * 1e3, v[13] * 1e3],
+        healthy: v[6] == 0.0,
+    }
+}
+
+/// Checks the header of a navigation file and returns its version.
+fn read_header<R: BufRead>(reader: &mut R, line: &mut String) -> Result<f64, RinexError> {
+    let mut version = None;
+    loop {
+        line.clear();
+        if reader.read_line(line).map_err(|_| RinexError::TruncatedHeader)? == 0 {
+            return Err(RinexError::TruncatedHeader);
+        }
+        let label = column(line, 60, 80);
+        if version.is_none() {
+            if label != "RINEX VERSION / TYPE" {
+                return Err(RinexError::NotRinex);
+            }
+            let v: f64 = column(line, 0, 9).parse().map_err(|_| RinexError::MalformedHeader("RINEX VERSION / TYPE"))?;
+            if column(line, 20, 21) != "N" {
+                return Err(RinexError::NotNavigationFile);
+            }
+            if !(3.0..4.0).contains(&v) {
+                return Err(RinexError::UnsupportedVersion(v));
+            }
+            version = Some(v);
+        } else if label == "END OF HEADER" {
+            return Ok(version.unwrap_or(3.0));
+        }
+    }
+}
+
+/// Reads the broadcast ephemerides of a RINEX 3 navigation file, mixed or
+/// for a single constellation. Unhealthy ephemerides are left out.
+pub fn read_navigation<R: Read>(reader: R) -> io::Result<NavLog> {
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+    let version = read_header(&mut reader, &mut line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let mut orbits = BroadcastOrbits::new();
+    let mut report = NavReport::default();
+    let mut lines: Vec<String> = Vec::new();
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let first = line.trim_end().to_string();
+        if first.is_empty() {
+            continue;
+        }
+
+        let system = first.chars().next().unwrap_or(' ');
+        let Some(n_lines) = orbit_lines(system, version) else {
+            report.malformed += 1;
+            continue;
+        };
+
+        lines.clear();
+        lines.push(first);
+        for _ in 0..n_lines {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                break;
+            }
+            lines.push(line.trim_end().to_string());
+        }
+        if lines.len() != n_lines + 1 {
+            report.malformed += 1;
+            break;
+        }
+
+        let network = system_to_network(system);
+        let prn = column(&lines[0], 1, 3).parse::<u32>().ok();
+        let (Some(network), Some(prn)) = (network, prn) else {
+            report.malformed += 1;
+            continue;
+        };
+        if network == Network::SBAS {
+            report.skipped += 1;
+            continue;
+        }
+
+        let time_system = if network == Network::GLONASS { TimeSystem::Utc } else { TimeSystem::Gps };
+        let (Some(epoch), Some(values)) = (record_epoch(&lines[0], time_system), record_values(&lines)) else {
+            report.malformed += 1;
+            continue;
+        };
+
+        let healthy = if network == Network::GLONASS {
+            let ephemeris = glonass_ephemeris(prn, epoch, &values);
+            let healthy = ephemeris.healthy;
+            orbits.push_glonass(ephemeris);
+            healthy
+        } else {
+            let ephemeris = kepler_ephemeris(network, prn, &values);
+            let healthy = ephemeris.healthy;
+            orbits.push_kepler(ephemeris);
+            healthy
+        };
+        if healthy {
+            report.ephemerides += 1;
+        } else {
+            report.unhealthy += 1;
+        }
+    }
+
+    Ok(NavLog { orbits, report, version })
+}
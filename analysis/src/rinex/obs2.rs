@@ -0,0 +1,85 @@
+use crate::time::Epoch;
+use super::{column, TimeSystem};
+
+/// Satellites listed on the epoch line itself; more continue on the next lines.
+pub const SATELLITES_PER_LINE: usize = 12;
+
+/// Observations per line, each F14.3 plus loss of lock and signal strength indicators.
+pub const OBSERVATIONS_PER_LINE: usize = 5;
+const OBSERVATION_WIDTH: usize = 16;
+
+/// A RINEX 2 epoch record, ` yy mm dd hh mm ss.sssssss  f nnn` followed by
+/// the first satellites of the epoch.
+pub struct EpochLine {
+    /// Missing on event records, which may leave the date blank.
+    pub time: Option<Epoch>,
+    pub flag: u8,
+    /// Satellites in the epoch, or special records following an event.
+    pub count: usize,
+    pub satellites: Vec<(char, u32)>,
+}
+
+pub fn parse_epoch_line(line: &str, time_system: TimeSystem) -> Option<EpochLine> {
+    let flag = column(line, 28, 29).parse::<u8>().ok()?;
+    let count = column(line, 29, 32).parse::<usize>().ok()?;
+
+    // Two-digit years, 80-99 being the 20th century.
+    let year = column(line, 1, 3).parse::<i32>().ok().map(|y| if y < 80 { 2000 + y } else { 1900 + y });
+    let month = column(line, 4, 6).parse::<u32>().ok();
+    let day = column(line, 7, 9).parse::<u32>().ok();
+    let hour = column(line, 10, 12).parse::<u32>().ok();
+    let minute = column(line, 13, 15).parse::<u32>().ok();
+    let seconds = column(line, 15, 26).parse::<f64>().ok();
+
+    let time = match (year, month, day, hour, minute, seconds) {
+        (Some(year), Some(month), Some(day), Some(hour), Some(minute), Some(seconds))
+            if (1..=12).contains(&month) && (1..=31).contains(&day) && hour < 24 && minute < 60 && (0.0..61.0).contains(&seconds) =>
+        {
+            Some(time_system.epoch(year, month, day, hour, minute, seconds))
+        }
+        // Observation epochs must be dated; event records may leave it blank.
+        _ if flag <= 1 => return None,
+        _ => None,
+    };
+
+    let mut satellites = Vec::with_capacity(count);
+    if flag <= 1 {
+        parse_satellite_list(line, count, &mut satellites)?;
+    }
+
+    Some(EpochLine { time, flag, count, satellites })
+}
+
+/// Reads the satellite ids in columns 32-68 of an epoch line or of its
+/// continuation, until `count` satellites are known.
+pub fn parse_satellite_list(line: &str, count: usize, satellites: &mut Vec<(char, u32)>) -> Option<()> {
+    let on_line = (count - satellites.len()).min(SATELLITES_PER_LINE);
+    for i in 0..on_line {
+        let start = 32 + 3 * i;
+        let id = line.get(start..start + 3)?;
+        // A blank system identifier means GPS.
+        let mut chars = id.chars();
+        let system = match chars.next()? {
+            ' ' => 'G',
+            system => system,
+        };
+        let prn = chars.as_str().trim().parse::<u32>().ok()?;
+        satellites.push((system, prn));
+    }
+    Some(())
+}
+
+/// Appends the observations on one line of a satellite, at most `remaining`
+/// of them. Blank observations are `None`.
+pub fn parse_observation_line(line: &str, remaining: usize, values: &mut Vec<Option<f64>>) -> Option<()> {
+    for i in 0..remaining.min(OBSERVATIONS_PER_LINE) {
+        let start = OBSERVATION_WIDTH * i;
+        let field = column(line, start, start + 14);
+        if field.is_empty() {
+            values.push(None);
+        } else {
+            values.push(Some(field.parse::<f64>().ok()?));
+        }
+    }
+    Some(())
+}
@@ -0,0 +1,60 @@
+use crate::time::Epoch;
+use super::{column, TimeSystem};
+
+/// Width of one observation: F14.3 value, loss of lock and signal strength indicators.
+const OBSERVATION_WIDTH: usize = 16;
+
+/// A RINEX 3 epoch record, `> yyyy mm dd hh mm ss.sssssss f nnn`.
+pub struct EpochLine {
+    /// Missing on event records, which may leave the date blank.
+    pub time: Option<Epoch>,
+    pub flag: u8,
+    /// Satellites in the epoch, or special records following an event.
+    pub count: usize,
+}
+
+pub fn parse_epoch_line(line: &str, time_system: TimeSystem) -> Option<EpochLine> {
+    let flag = column(line, 31, 32).parse::<u8>().ok()?;
+    let count = column(line, 32, 35).parse::<usize>().ok()?;
+
+    let year = column(line, 2, 6).parse::<i32>().ok();
+    let month = column(line, 7, 9).parse::<u32>().ok();
+    let day = column(line, 10, 12).parse::<u32>().ok();
+    let hour = column(line, 13, 15).parse::<u32>().ok();
+    let minute = column(line, 16, 18).parse::<u32>().ok();
+    let seconds = column(line, 18, 29).parse::<f64>().ok();
+
+    let time = match (year, month, day, hour, minute, seconds) {
+        (Some(year), Some(month), Some(day), Some(hour), Some(minute), Some(seconds))
+            if (1..=12).contains(&month) && (1..=31).contains(&day) && hour < 24 && minute < 60 && (0.0..61.0).contains(&seconds) =>
+        {
+            Some(time_system.epoch(year, month, day, hour, minute, seconds))
+        }
+        // Observation epochs must be dated; event records may leave it blank.
+        _ if flag <= 1 => return None,
+        _ => None,
+    };
+
+    Some(EpochLine { time, flag, count })
+}
+
+/// Splits a satellite line into its system letter, PRN and observation values
+/// in header order. Blank observations are `None`.
+pub fn parse_satellite_line(line: &str) -> Option<(char, u32, Vec<Option<f64>>)> {
+    let system = line.chars().next()?;
+    let prn = column(line, 1, 3).parse::<u32>().ok()?;
+
+    let n_values = line.len().saturating_sub(3).div_ceil(OBSERVATION_WIDTH);
+    let mut values = Vec::with_capacity(n_values);
+    for i in 0..n_values {
+        let start = 3 + OBSERVATION_WIDTH * i;
+        let field = column(line, start, start + 14);
+        if field.is_empty() {
+            values.push(None);
+        } else {
+            values.push(Some(field.parse::<f64>().ok()?));
+        }
+    }
+
+    Some((system, prn, values))
+}
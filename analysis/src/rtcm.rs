@@ -0,0 +1,569 @@
+use std::fmt;
+use std::io::{self, Read};
+
+use crate::config::Config;
+use crate::db::record::{Band, Network, Quality, Record};
+use crate::rinex::band_from_code;
+use crate::station::{StationMetadata, StationPosition};
+use crate::source::StreamSource;
+use crate::stream::RecordParser;
+use crate::time::{Epoch, MILLIS_PER_DAY};
+
+const PREAMBLE: u8 = 0xD3;
+const HEADER_LENGTH: usize = 3;
+const CRC_LENGTH: usize = 3;
+
+/// CRC-24Q generator polynomial.
+const CRC24Q: u32 = 0x0186_4CFB;
+
+const MILLIS_PER_WEEK: i64 = 7 * MILLIS_PER_DAY;
+
+/// GLONASS time runs three hours ahead of UTC; BeiDou time 14 s behind GPS time.
+const GLONASS_UTC_OFFSET_MILLIS: i64 = 3 * 3_600_000;
+const BEIDOU_GPS_OFFSET_MILLIS: i64 = 14_000;
+
+/// Counts of how the frames of an RTCM 3 stream were handled.
+#[derive(Debug, Clone, Default)]
+pub struct RtcmReport {
+    pub frames: usize,
+    pub bad_crc: usize,
+    pub skipped_bytes: usize,
+    pub truncated: usize,
+    pub msm: usize,
+    pub station: usize,
+    pub ignored: usize,
+}
+
+impl fmt::Display for RtcmReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} frames (MSM: {}, 1005/1006: {}, ignored: {}), {} bad CRCs, {} truncated, {} bytes skipped while syncing",
+            self.frames, self.msm, self.station, self.ignored, self.bad_crc, self.truncated, self.skipped_bytes,
+        )
+    }
+}
+
+/// CRC-24Q over the header and payload of a frame.
+fn crc24q(data: &[u8]) -> u32 {
+    data.iter().fold(0u32, |crc, &byte| {
+        let mut crc = crc ^ ((byte as u32) << 16);
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x0100_0000 != 0 {
+                crc ^= CRC24Q;
+            }
+        }
+        crc & 0x00FF_FFFF
+    })
+}
+
+/// `length` bits (at most 64) starting at bit `start`, most significant bit first.
+fn bits(data: &[u8], start: usize, length: usize) -> u64 {
+    (start..start + length).fold(0u64, |value, i| (value << 1) | ((data[i / 8] >> (7 - i % 8)) & 1) as u64)
+}
+
+/// Two's complement signed field.
+fn signed_bits(data: &[u8], start: usize, length: usize) -> i64 {
+    let value = bits(data, start, length);
+    ((value << (64 - length)) as i64) >> (64 - length)
+}
+
+/// Network of an MSM message type, 1071-1077 for GPS up to 1131-1137 for NavIC.
+fn msm_network(message_type: u16) -> Option<Network> {
+    let network = match message_type / 10 {
+        107 => Network::GPS,
+        108 => Network::GLONASS,
+        109 => Network::Galileo,
+        110 => Network::SBAS,
+        111 => Network::QZSS,
+        112 => Network::BeiDou,
+        113 => Network::NavIC,
+        _ => return None,
+    };
+    Some(network)
+}
+
+/// RINEX observation code of an MSM signal id (RTCM 10403 signal tables), so
+/// the bands follow the same rules as for RINEX input.
+fn signal_code(network: Network, signal: u32) -> Option<&'static str> {
+    let code = match (network, signal) {
+        (Network::GPS, 2) => "1C",
+        (Network::GPS, 3) => "1P",
+        (Network::GPS, 4) => "1W",
+        (Network::GPS, 8) => "2C",
+        (Network::GPS, 9) => "2P",
+        (Network::GPS, 10) => "2W",
+        (Network::GPS, 15) => "2S",
+        (Network::GPS, 16) => "2L",
+        (Network::GPS, 17) => "2X",
+        (Network::GPS | Network::QZSS | Network::SBAS, 22) => "5I",
+        (Network::GPS | Network::QZSS | Network::SBAS, 23) => "5Q",
+        (Network::GPS | Network::QZSS | Network::SBAS, 24) => "5X",
+        (Network::GPS | Network::QZSS, 30) => "1S",
+        (Network::GPS | Network::QZSS, 31) => "1L",
+        (Network::GPS | Network::QZSS, 32) => "1X",
+        (Network::GLONASS, 2) => "1C",
+        (Network::GLONASS, 3) => "1P",
+        (Network::GLONASS, 8) => "2C",
+        (Network::GLONASS, 9) => "2P",
+        (Network::Galileo, 2) => "1C",
+        (Network::Galileo, 3) => "1A",
+        (Network::Galileo, 4) => "1B",
+        (Network::Galileo, 5) => "1X",
+        (Network::Galileo, 6) => "1Z",
+        (Network::Galileo, 8) => "6C",
+        (Network::Galileo, 9) => "6A",
+        (Network::Galileo, 10) => "6B",
+        (Network::Galileo, 11) => "6X",
+        (Network::Galileo, 12) => "6Z",
+        (Network::Galileo, 14) => "7I",
+        (Network::Galileo, 15) => "7Q",
+        (Network::Galileo, 16) => "7X",
+        (Network::Galileo, 18) => "8I",
+        (Network::Galileo, 19) => "8Q",
+        (Network::Galileo, 20) => "8X",
+        (Network::Galileo, 22) => "5I",
+        (Network::Galileo, 23) => "5Q",
+        (Network::Galileo, 24) => "5X",
+        (Network::SBAS | Network::QZSS, 2) => "1C",
+        (Network::QZSS, 9) => "6S",
+        (Network::QZSS, 10) => "6L",
+        (Network::QZSS, 11) => "6X",
+        (Network::QZSS, 15) => "2S",
+        (Network::QZSS, 16) => "2L",
+        (Network::QZSS, 17) => "2X",
+        (Network::BeiDou, 2) => "2I",
+        (Network::BeiDou, 3) => "2Q",
+        (Network::BeiDou, 4) => "2X",
+        (Network::BeiDou, 8) => "6I",
+        (Network::BeiDou, 9) => "6Q",
+        (Network::BeiDou, 10) => "6X",
+        (Network::BeiDou, 14) => "7I",
+        (Network::BeiDou, 15) => "7Q",
+        (Network::BeiDou, 16) => "7X",
+        (Network::BeiDou, 22) => "5D",
+        (Network::BeiDou, 23) => "5P",
+        (Network::BeiDou, 24) => "5X",
+        (Network::BeiDou, 25) => "7D",
+        (Network::BeiDou, 30) => "1D",
+        (Network::BeiDou, 31) => "1P",
+        (Network::BeiDou, 32) => "1X",
+        (Network::NavIC, 8) => "9A",
+        (Network::NavIC, 22) => "5A",
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// Bit widths of an MSM message: satellite data per satellite, and the
+/// signal fields in front of the CNR, the CNR itself and its scale (dB-Hz).
+struct MsmLayout {
+    satellite_bits: usize,
+    bits_before_cnr: usize,
+    cnr_bits: usize,
+    cnr_scale: f64,
+}
+
+fn msm_layout(kind: u16) -> Option<MsmLayout> {
+    let layout = match kind {
+        4 => MsmLayout { satellite_bits: 18, bits_before_cnr: 15 + 22 + 4 + 1, cnr_bits: 6, cnr_scale: 1.0 },
+        5 => MsmLayout { satellite_bits: 36, bits_before_cnr: 15 + 22 + 4 + 1, cnr_bits: 6, cnr_scale: 1.0 },
+        6 => MsmLayout { satellite_bits: 18, bits_before_cnr: 20 + 24 + 10 + 1, cnr_bits: 10, cnr_scale: 0.0625 },
+        7 => MsmLayout { satellite_bits: 36, bits_before_cnr: 20 + 24 + 10 + 1, cnr_bits: 10, cnr_scale: 0.0625 },
+        _ => return None,
+    };
+    Some(layout)
+}
+
+/// Bits of the MSM header in front of the satellite mask.
+const MSM_HEADER_BITS: usize = 12 + 12 + 30 + 1 + 3 + 7 + 2 + 2 + 1 + 3;
+
+/// Incremental RTCM 3 decoder. Frames are located by their preamble and
+/// checked against their CRC; after a bad frame the decoder resyncs on the
+/// next preamble. MSM4 to MSM7 messages produce one record per satellite and
+/// band; 1005/1006 give the station position.
+///
+/// MSM epochs only carry the time of week (or of day, for GLONASS), so the
+/// parser needs a time close to the start of the stream to date them. It is
+/// carried forward as the stream goes on.
+///
+/// MSM carries no elevation or azimuth, so its records are left NaN there
+/// and only form arcs once geometry is computed from orbits (`--nav` or
+/// `--sp3`).
+pub struct RtcmParser {
+    config: Config,
+    buffer: Vec<u8>,
+    reference: Epoch,
+    station: StationMetadata,
+    report: RtcmReport,
+}
+
+impl RtcmParser {
+    /// `reference` is a time within half a week of the first MSM epoch, e.g.
+    /// the current time for a live stream.
+    pub fn new(config: &Config, reference: Epoch) -> Self {
+        RtcmParser {
+            config: config.clone(),
+            buffer: Vec::new(),
+            reference,
+            station: StationMetadata::default(),
+            report: RtcmReport::default(),
+        }
+    }
+
+    pub fn report(&self) -> &RtcmReport {
+        &self.report
+    }
+
+    pub fn station(&self) -> &StationMetadata {
+        &self.station
+    }
+
+    /// Extracts all complete frames from the buffer, keeping a trailing partial one.
+    fn drain_frames(&mut self, records: &mut Vec<Record>) {
+        let mut start = 0;
+        loop {
+            let rest = &self.buffer[start..];
+            let sync = match rest.iter().position(|&b| b == PREAMBLE) {
+                Some(i) => i,
+                None => {
+                    self.report.skipped_bytes += rest.len();
+                    start = self.buffer.len();
+                    break;
+                }
+            };
+            self.report.skipped_bytes += sync;
+            start += sync;
+
+            let rest = &self.buffer[start..];
+            if rest.len() < HEADER_LENGTH {
+                break;
+            }
+            // Six reserved bits, which must be zero, and a ten bit length.
+            if rest[1] & 0xFC != 0 {
+                self.report.skipped_bytes += 1;
+                start += 1;
+                continue;
+            }
+            let length = ((rest[1] as usize & 0x03) << 8) | rest[2] as usize;
+            let frame_length = HEADER_LENGTH + length + CRC_LENGTH;
+            if rest.len() < frame_length {
+                break;
+            }
+
+            let crc = bits(&rest[HEADER_LENGTH + length..frame_length], 0, 24) as u32;
+            if crc24q(&rest[..HEADER_LENGTH + length]) != crc {
+                // Possibly a false preamble inside other data: only skip that byte.
+                self.report.bad_crc += 1;
+                self.report.skipped_bytes += 1;
+                start += 1;
+                continue;
+            }
+
+            let payload = rest[HEADER_LENGTH..HEADER_LENGTH + length].to_vec();
+            self.report.frames += 1;
+            self.handle_message(&payload, records);
+            start += frame_length;
+        }
+        self.buffer.drain(..start);
+    }
+
+    fn handle_message(&mut self, payload: &[u8], records: &mut Vec<Record>) {
+        if payload.len() < 2 {
+            self.report.truncated += 1;
+            return;
+        }
+        let message_type = bits(payload, 0, 12) as u16;
+        match message_type {
+            1005 | 1006 => self.handle_station(message_type, payload),
+            _ => match (msm_network(message_type), msm_layout(message_type % 10)) {
+                (Some(network), Some(layout)) => self.handle_msm(network, &layout, payload, records),
+                _ => self.report.ignored += 1,
+            },
+        }
+    }
+
+    /// Stationary RTK reference station ARP, with the antenna height in 1006.
+    fn handle_station(&mut self, message_type: u16, p: &[u8]) {
+        let bits_needed = if message_type == 1006 { 168 } else { 152 };
+        if p.len() * 8 < bits_needed {
+            self.report.truncated += 1;
+            return;
+        }
+        self.report.station += 1;
+
+        let x = signed_bits(p, 34, 38) as f64 * 1e-4;
+        let y = signed_bits(p, 74, 38) as f64 * 1e-4;
+        let z = signed_bits(p, 114, 38) as f64 * 1e-4;
+        self.station.position = Some(StationPosition::from_ecef(x, y, z));
+        if message_type == 1006 {
+            self.station.antenna_height = Some(bits(p, 152, 16) as f64 * 1e-4);
+        }
+    }
+
+    /// The time of week (GPS time, ms) nearest to the reference time.
+    fn resolve_time_of_week(&mut self, tow_millis: i64) -> Epoch {
+        let week = (self.reference.gps_seconds() / (MILLIS_PER_WEEK / 1000) as f64).floor() as i64;
+        let time = (week - 1..=week + 1)
+            .filter(|&w| w >= 0)
+            .map(|w| Epoch::from_gps_week_millis(w as u32, tow_millis))
+            .min_by(|a, b| (*a - self.reference).abs().total_cmp(&(*b - self.reference).abs()))
+            .unwrap_or(self.reference);
+        self.reference = time;
+        time
+    }
+
+    fn msm_epoch(&mut self, network: Network, epoch_field: u64) -> Epoch {
+        match network {
+            Network::GLONASS => {
+                let day_of_week = (epoch_field >> 27) as i64;
+                let millis_of_day = (epoch_field & 0x07FF_FFFF) as i64 - GLONASS_UTC_OFFSET_MILLIS;
+                if day_of_week < 7 {
+                    // GLONASS weeks start on Sunday, like GPS weeks.
+                    let utc_tow = day_of_week * MILLIS_PER_DAY + millis_of_day;
+                    let gps_tow = utc_tow + self.reference.leap_seconds() * 1000;
+                    return self.resolve_time_of_week(gps_tow.rem_euclid(MILLIS_PER_WEEK));
+                }
+                // Unknown day: take the UTC day nearest to the reference time.
+                let day = self.reference.unix_utc_millis().div_euclid(MILLIS_PER_DAY);
+                let time = (day - 1..=day + 1)
+                    .map(|d| Epoch::from_utc_days(d, millis_of_day))
+                    .min_by(|a, b| (*a - self.reference).abs().total_cmp(&(*b - self.reference).abs()))
+                    .unwrap_or(self.reference);
+                self.reference = time;
+                time
+            }
+            Network::BeiDou => {
+                let tow = (epoch_field as i64 + BEIDOU_GPS_OFFSET_MILLIS).rem_euclid(MILLIS_PER_WEEK);
+                self.resolve_time_of_week(tow)
+            }
+            _ => self.resolve_time_of_week(epoch_field as i64),
+        }
+    }
+
+    fn handle_msm(&mut self, network: Network, layout: &MsmLayout, p: &[u8], records: &mut Vec<Record>) {
+        let total_bits = p.len() * 8;
+        if total_bits < MSM_HEADER_BITS + 96 {
+            self.report.truncated += 1;
+            return;
+        }
+
+        let satellite_mask = bits(p, MSM_HEADER_BITS, 64);
+        let signal_mask = bits(p, MSM_HEADER_BITS + 64, 32) as u32;
+        let satellites: Vec<u32> = (1..=64).filter(|i| satellite_mask & (1 << (64 - i)) != 0).collect();
+        let signals: Vec<u32> = (1..=32).filter(|i| signal_mask & (1 << (32 - i)) != 0).collect();
+
+        let cell_mask_bits = satellites.len() * signals.len();
+        if cell_mask_bits > 64 || total_bits < MSM_HEADER_BITS + 96 + cell_mask_bits {
+            self.report.truncated += 1;
+            return;
+        }
+        let cell_mask_start = MSM_HEADER_BITS + 96;
+        let cells: Vec<(u32, u32)> = (0..cell_mask_bits)
+            .filter(|&i| bits(p, cell_mask_start + i, 1) == 1)
+            .map(|i| (satellites[i / signals.len()], signals[i % signals.len()]))
+            .collect();
+
+        let signal_data_start = cell_mask_start + cell_mask_bits + layout.satellite_bits * satellites.len();
+        let cnr_start = signal_data_start + layout.bits_before_cnr * cells.len();
+        if total_bits < cnr_start + layout.cnr_bits * cells.len() {
+            self.report.truncated += 1;
+            return;
+        }
+        self.report.msm += 1;
+
+        let time = self.msm_epoch(network, bits(p, 24, 30));
+        if !self.config.networks.contains(&network) {
+            return;
+        }
+
+        // Several signals can share a band (e.g. L2C and L2W): the first one listed wins.
+        let mut seen: Vec<(u32, Band)> = Vec::new();
+        for (i, &(prn, signal)) in cells.iter().enumerate() {
+            let cnr = bits(p, cnr_start + layout.cnr_bits * i, layout.cnr_bits) as f64 * layout.cnr_scale;
+            // A CNR of zero means the value is not available.
+            if cnr <= 0.0 {
+                continue;
+            }
+            let Some(code) = signal_code(network, signal) else {
+                continue;
+            };
+            let band = band_from_code(network, &format!("S{}", code), 3.04);
+            let satellite = match network {
+                // SBAS MSM satellite ids count from PRN 120.
+                Network::SBAS => prn + 119,
+                _ => prn,
+            };
+            if seen.contains(&(satellite, band)) {
+                continue;
+            }
+            seen.push((satellite, band));
+
+            records.push(Record {
+                id: Record::make_id(network, band, satellite),
+                satellite,
+                elevation: f64::NAN,
+                azimuth: f64::NAN,
+                snr: cnr,
+                time,
+                network,
+                band,
+                quality: Quality::default(),
+            });
+        }
+    }
+}
+
+impl RecordParser for RtcmParser {
+    fn push_bytes(&mut self, chunk: &[u8], records: &mut Vec<Record>) {
+        self.buffer.extend_from_slice(chunk);
+        self.drain_frames(records);
+    }
+
+    fn finish(&mut self, records: &mut Vec<Record>) {
+        if !self.buffer.is_empty() {
+            self.report.truncated += 1;
+        }
+        // A false preamble near the end waits for bytes that never come: skip
+        // it and look for frames in the rest.
+        while !self.buffer.is_empty() {
+            self.buffer.remove(0);
+            self.report.skipped_bytes += 1;
+            self.drain_frames(records);
+        }
+    }
+
+    fn station(&self) -> StationMetadata {
+        self.station.clone()
+    }
+
+    fn summary(&self) -> String {
+        format!("RTCM 3: {}", self.report)
+    }
+}
+
+/// Everything extracted from a complete RTCM 3 stream.
+pub struct RtcmLog {
+    pub records: Vec<Record>,
+    pub report: RtcmReport,
+    pub station: StationMetadata,
+}
+
+/// Parses a complete RTCM 3 stream, e.g. a logged caster stream, with a
+/// `reference` time within half a week of its start.
+pub fn read_records<R: Read>(reader: R, config: &Config, reference: Epoch) -> io::Result<RtcmLog> {
+    let mut source = StreamSource::new(reader, RtcmParser::new(config, reference));
+    let records = source.read_all()?;
+    let parser = source.parser();
+    Ok(RtcmLog {
+        records,
+        report: parser.report().clone(),
+        station: parser.station().clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The message 1005 example of RTCM 10403.3, station 2003.
+    const EXAMPLE_1005: [u8; 25] = [
+        0xD3, 0x00, 0x13, 0x3E, 0xD7, 0xD3, 0x02, 0x02, 0x98, 0x0E, 0xDE, 0xEF, 0x34,
+        0xB4, 0xBD, 0x62, 0xAC, 0x09, 0x41, 0x98, 0x6F, 0x33, 0x36, 0x0B, 0x98,
+    ];
+
+    #[derive(Default)]
+    struct BitWriter {
+        bytes: Vec<u8>,
+        length: usize,
+    }
+
+    impl BitWriter {
+        /// Appends the low `width` bits of `value`; wider fields are zero padded.
+        fn push(&mut self, value: u64, width: usize) {
+            for i in (0..width).rev() {
+                if self.length.is_multiple_of(8) {
+                    self.bytes.push(0);
+                }
+                let bit = value.checked_shr(i as u32).map_or(0, |v| (v & 1) as u8);
+                *self.bytes.last_mut().unwrap() |= bit << (7 - self.length % 8);
+                self.length += 1;
+            }
+        }
+    }
+
+    fn frame(payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![PREAMBLE, (payload.len() >> 8) as u8, payload.len() as u8];
+        frame.extend_from_slice(payload);
+        let crc = crc24q(&frame);
+        frame.extend_from_slice(&crc.to_be_bytes()[1..]);
+        frame
+    }
+
+    /// MSM7 GPS message at `tow_millis`: G05 with L1 C/A and L2C, G12 with
+    /// L1 C/A only, the CNRs in that cell order.
+    fn msm7(tow_millis: u64, cnr: [f64; 3]) -> Vec<u8> {
+        let mut w = BitWriter::default();
+        w.push(1077, 12);
+        w.push(2003, 12);
+        w.push(tow_millis, 30);
+        w.push(0, MSM_HEADER_BITS - 54);
+        w.push((1 << (64 - 5)) | (1 << (64 - 12)), 64);
+        w.push((1 << (32 - 2)) | (1 << (32 - 16)), 32);
+        w.push(0b1110, 4);
+        w.push(0, 2 * 36);
+        w.push(0, 3 * (20 + 24 + 10 + 1));
+        for value in cnr {
+            w.push((value / 0.0625) as u64, 10);
+        }
+        w.push(0, 3 * 15);
+        frame(&w.bytes)
+    }
+
+    fn parse(bytes: &[u8]) -> RtcmLog {
+        let reference = Epoch::from_gps_week_millis(2295, 0);
+        read_records(bytes, &Config::default(), reference).unwrap()
+    }
+
+    #[test]
+    fn crc24q_of_the_standard_example() {
+        assert_eq!(crc24q(&EXAMPLE_1005[..22]), 0x36_0B98);
+        assert_eq!(crc24q(&EXAMPLE_1005), 0);
+    }
+
+    #[test]
+    fn station_position_from_1005() {
+        let log = parse(&EXAMPLE_1005);
+        assert_eq!((log.report.frames, log.report.station), (1, 1));
+        let (x, y, z) = log.station.position.unwrap().ecef();
+        assert!((x - 1_114_104.599_9).abs() < 1e-3);
+        assert!((y + 4_850_729.710_8).abs() < 1e-3);
+        assert!((z - 3_975_521.464_3).abs() < 1e-3);
+    }
+
+    #[test]
+    fn msm7_cells_become_records() {
+        let log = parse(&msm7(100_000_000, [45.0625, 38.5, 41.0]));
+        assert_eq!((log.report.frames, log.report.msm, log.report.bad_crc), (1, 1, 0));
+        let time = Epoch::from_gps_week_millis(2295, 100_000_000);
+        let records: Vec<_> = log.records.iter().map(|r| (r.satellite, r.band, r.snr, r.time)).collect();
+        assert_eq!(records, vec![
+            (5, Band::L1, 45.0625, time),
+            (5, Band::L2, 38.5, time),
+            (12, Band::L1, 41.0, time),
+        ]);
+    }
+
+    #[test]
+    fn corrupted_frame_is_dropped_and_decoder_resyncs() {
+        let mut corrupted = msm7(100_000_000, [45.0, 38.0, 41.0]);
+        corrupted[10] ^= 0x10;
+        let garbage = [0x00, 0xD3, 0xFF, 0x12, 0xD3];
+        let bytes = [&corrupted[..], &garbage, &msm7(100_001_000, [46.0, 39.0, 42.0])].concat();
+        let log = parse(&bytes);
+        assert_eq!((log.report.frames, log.report.bad_crc), (1, 1));
+        assert!(log.report.skipped_bytes >= garbage.len());
+        assert_eq!(log.records.iter().map(|r| r.snr).collect::<Vec<_>>(), vec![46.0, 39.0, 42.0]);
+    }
+}
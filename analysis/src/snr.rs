@@ -0,0 +1,408 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::config::Config;
+use crate::db::record::{Band, Network, Quality, Record};
+use crate::source::StreamSource;
+use crate::stream::RecordParser;
+use crate::time::{days_from_civil, Epoch};
+
+/// SNR columns after sat, elevation, azimuth, seconds and elevation rate:
+/// S6, S1, S2, S5, S7, S8.
+const SNR_COLUMNS: usize = 6;
+const SNR_DIGITS: [u32; SNR_COLUMNS] = [6, 1, 2, 5, 7, 8];
+
+/// Longest gap (s) between two records of a satellite still used for its elevation rate.
+const MAX_RATE_GAP: f64 = 60.0;
+
+/// Elevation selections of gnssrefl, named by the number in the file extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnrType {
+    /// `snr50`: below 10 degrees.
+    Snr50,
+    /// `snr66`: below 30 degrees.
+    Snr66,
+    /// `snr88`: all elevations.
+    Snr88,
+    /// `snr99`: 5 to 30 degrees.
+    Snr99,
+}
+
+impl SnrType {
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        let snr_type = match extension {
+            "snr50" => SnrType::Snr50,
+            "snr66" => SnrType::Snr66,
+            "snr88" => SnrType::Snr88,
+            "snr99" => SnrType::Snr99,
+            _ => return None,
+        };
+        Some(snr_type)
+    }
+
+    fn accepts_elevation(&self, elevation: f64) -> bool {
+        match self {
+            SnrType::Snr50 => elevation < 10.0,
+            SnrType::Snr66 => elevation < 30.0,
+            SnrType::Snr88 => true,
+            SnrType::Snr99 => (5.0..=30.0).contains(&elevation),
+        }
+    }
+}
+
+/// gnssrefl satellite number: GPS as is, GLONASS +100, Galileo +200, BeiDou +300.
+fn satellite_number(network: Network, satellite: u32) -> Option<u32> {
+    match network {
+        Network::GPS => Some(satellite),
+        Network::GLONASS => Some(satellite + 100),
+        Network::Galileo => Some(satellite + 200),
+        Network::BeiDou => Some(satellite + 300),
+        _ => None,
+    }
+}
+
+fn network_of_number(number: u32) -> Option<(Network, u32)> {
+    let satellite = match number {
+        1..=99 => (Network::GPS, number),
+        101..=199 => (Network::GLONASS, number - 100),
+        201..=299 => (Network::Galileo, number - 200),
+        301..=399 => (Network::BeiDou, number - 300),
+        _ => return None,
+    };
+    Some(satellite)
+}
+
+/// The RINEX frequency digit gnssrefl files a band under.
+fn band_digit(network: Network, band: Band) -> Option<u32> {
+    let digit = match (network, band) {
+        (Network::GPS, Band::L1) | (Network::GLONASS, Band::G1) | (Network::Galileo, Band::E1) => 1,
+        (Network::GPS, Band::L2) | (Network::GLONASS, Band::G2) => 2,
+        (Network::GPS, Band::L5) | (Network::Galileo, Band::E5a) => 5,
+        (Network::Galileo, Band::E6) => 6,
+        (Network::Galileo, Band::E5b) => 7,
+        (Network::Galileo, Band::E5) => 8,
+        (Network::BeiDou, Band::B1C) => 1,
+        (Network::BeiDou, Band::B1I) => 2,
+        (Network::BeiDou, Band::B2a) => 5,
+        (Network::BeiDou, Band::B3) => 6,
+        (Network::BeiDou, Band::B2b) => 7,
+        (Network::BeiDou, Band::B2) => 8,
+        _ => return None,
+    };
+    Some(digit)
+}
+
+fn digit_band(network: Network, digit: u32) -> Option<Band> {
+    let band = match (network, digit) {
+        (Network::GPS, 1) => Band::L1,
+        (Network::GPS, 2) => Band::L2,
+        (Network::GPS, 5) => Band::L5,
+        (Network::GLONASS, 1) => Band::G1,
+        (Network::GLONASS, 2) => Band::G2,
+        (Network::Galileo, 1) => Band::E1,
+        (Network::Galileo, 5) => Band::E5a,
+        (Network::Galileo, 6) => Band::E6,
+        (Network::Galileo, 7) => Band::E5b,
+        (Network::Galileo, 8) => Band::E5,
+        (Network::BeiDou, 1) => Band::B1C,
+        (Network::BeiDou, 2) => Band::B1I,
+        (Network::BeiDou, 5) => Band::B2a,
+        (Network::BeiDou, 6) => Band::B3,
+        (Network::BeiDou, 7) => Band::B2b,
+        (Network::BeiDou, 8) => Band::B2,
+        _ => return None,
+    };
+    Some(band)
+}
+
+/// GPS day (days since 1970-01-01) of a gnssrefl file named `ssssddd0.yy.snrNN`.
+pub fn day_from_file_name(file_name: &str) -> Option<i64> {
+    let name = file_name.rsplit('/').next()?;
+    let doy = name.get(4..7)?.parse::<i64>().ok()?;
+    let year = name.get(9..11)?.parse::<i32>().ok()?;
+    let year = if year < 80 { 2000 + year } else { 1900 + year };
+    if !(1..=366).contains(&doy) {
+        return None;
+    }
+    Some(days_from_civil(year, 1, 1) + doy - 1)
+}
+
+/// Counts of what was written to or read from an SNR file.
+#[derive(Debug, Clone, Default)]
+pub struct SnrReport {
+    pub lines: usize,
+    pub malformed_lines: usize,
+    pub unsupported_records: usize,
+    pub without_geometry: usize,
+    pub outside_elevation_mask: usize,
+    pub excluded_satellites: usize,
+}
+
+impl fmt::Display for SnrReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} lines, {} malformed, {} records of unsupported signals, {} without geometry, {} outside the elevation mask, {} excluded satellite observations",
+            self.lines,
+            self.malformed_lines,
+            self.unsupported_records,
+            self.without_geometry,
+            self.outside_elevation_mask,
+            self.excluded_satellites,
+        )
+    }
+}
+
+struct SnrLine {
+    elevation: f64,
+    azimuth: f64,
+    snr: [f64; SNR_COLUMNS],
+}
+
+/// Writes records as a gnssrefl SNR file: one line per satellite and epoch,
+/// with the seconds of the GPS day. Records should cover a single day.
+pub fn write_records<'a, W: Write>(
+    writer: W,
+    records: impl IntoIterator<Item = &'a Record>,
+    snr_type: SnrType,
+) -> io::Result<SnrReport> {
+    let mut report = SnrReport::default();
+    let mut lines: BTreeMap<(u32, Epoch), SnrLine> = BTreeMap::new();
+
+    for record in records {
+        let number = satellite_number(record.network, record.satellite);
+        let column = band_digit(record.network, record.band)
+            .and_then(|digit| SNR_DIGITS.iter().position(|&d| d == digit));
+        let (number, column) = match (number, column) {
+            (Some(number), Some(column)) => (number, column),
+            _ => {
+                report.unsupported_records += 1;
+                continue;
+            }
+        };
+        if record.elevation.is_nan() || record.azimuth.is_nan() {
+            report.without_geometry += 1;
+            continue;
+        }
+        if !snr_type.accepts_elevation(record.elevation) {
+            report.outside_elevation_mask += 1;
+            continue;
+        }
+
+        let line = lines.entry((number, record.time)).or_insert(SnrLine {
+            elevation: record.elevation,
+            azimuth: record.azimuth,
+            snr: [0.0; SNR_COLUMNS],
+        });
+        line.snr[column] = record.snr;
+    }
+
+    // Elevation rate from the neighbouring epochs of the same satellite.
+    let keys: Vec<(u32, Epoch)> = lines.keys().copied().collect();
+    let mut rates = Vec::with_capacity(keys.len());
+    for (i, &(number, time)) in keys.iter().enumerate() {
+        let neighbour = |j: Option<usize>| {
+            j.and_then(|j| keys.get(j))
+                .filter(|&&(n, t)| n == number && (t - time).abs() <= MAX_RATE_GAP)
+                .copied()
+        };
+        let before = neighbour(i.checked_sub(1)).unwrap_or((number, time));
+        let after = neighbour(Some(i + 1)).unwrap_or((number, time));
+        let dt = after.1 - before.1;
+        rates.push(if dt > 0.0 { (lines[&after].elevation - lines[&before].elevation) / dt } else { 0.0 });
+    }
+
+    // gnssrefl files are sorted by time, satellites within an epoch by number.
+    let mut order: Vec<usize> = (0..keys.len()).collect();
+    order.sort_by_key(|&i| (keys[i].1, keys[i].0));
+
+    let mut writer = io::BufWriter::new(writer);
+    for i in order {
+        let (number, time) = keys[i];
+        let line = &lines[&keys[i]];
+        let seconds = time.gps_millis_of_day() as f64 / 1000.0;
+        write!(writer, "{:3} {:10.4} {:10.4} {:10.3} {:10.6}", number, line.elevation, line.azimuth, seconds, rates[i])?;
+        for snr in line.snr {
+            write!(writer, " {:7.2}", snr)?;
+        }
+        writeln!(writer)?;
+        report.lines += 1;
+    }
+    writer.flush()?;
+    Ok(report)
+}
+
+/// Longest line kept while waiting for its newline.
+const MAX_LINE_LENGTH: usize = 256;
+
+/// Incremental reader of gnssrefl SNR files. The files only hold seconds of
+/// the day, so the GPS day has to be known up front.
+pub struct SnrParser {
+    config: Config,
+    day: i64,
+    buffer: Vec<u8>,
+    report: SnrReport,
+}
+
+impl SnrParser {
+    /// `day` is the GPS day the file covers, in days since 1970-01-01.
+    pub fn new(config: &Config, day: i64) -> Self {
+        SnrParser {
+            config: config.clone(),
+            day,
+            buffer: Vec::with_capacity(MAX_LINE_LENGTH),
+            report: SnrReport::default(),
+        }
+    }
+
+    pub fn report(&self) -> &SnrReport {
+        &self.report
+    }
+
+    fn push_line(&mut self, line: &[u8], records: &mut Vec<Record>) {
+        let line = String::from_utf8_lossy(line);
+        if line.trim().is_empty() {
+            return;
+        }
+        self.report.lines += 1;
+
+        let values: Option<Vec<f64>> = line.split_whitespace().map(|v| v.parse::<f64>().ok()).collect();
+        let values = match values {
+            Some(values) if values.len() >= 5 + SNR_COLUMNS => values,
+            _ => {
+                self.report.malformed_lines += 1;
+                return;
+            }
+        };
+
+        let (network, satellite) = match network_of_number(values[0] as u32) {
+            Some(sat) => sat,
+            None => {
+                self.report.unsupported_records += 1;
+                return;
+            }
+        };
+        if !self.config.networks.contains(&network) {
+            self.report.excluded_satellites += 1;
+            return;
+        }
+        let (elevation, azimuth) = (values[1], values[2]);
+        if !self.config.accepts_geometry(elevation, azimuth) {
+            self.report.outside_elevation_mask += 1;
+            return;
+        }
+        let time = Epoch::from_gps_days(self.day, (values[3] * 1000.0).round() as i64);
+
+        for (column, &snr) in values[5..5 + SNR_COLUMNS].iter().enumerate() {
+            // Zero marks a signal that was not tracked.
+            if snr <= 0.0 {
+                continue;
+            }
+            let band = match digit_band(network, SNR_DIGITS[column]) {
+                Some(band) => band,
+                None => continue,
+            };
+            records.push(Record {
+                id: Record::make_id(network, band, satellite),
+                satellite,
+                elevation,
+                azimuth,
+                snr,
+                time,
+                network,
+                band,
+                quality: Quality::default(),
+            });
+        }
+    }
+}
+
+impl RecordParser for SnrParser {
+    fn push_bytes(&mut self, chunk: &[u8], records: &mut Vec<Record>) {
+        for &byte in chunk {
+            if byte == b'\n' {
+                let line = std::mem::take(&mut self.buffer);
+                self.push_line(&line, records);
+                self.buffer = line;
+                self.buffer.clear();
+            } else if self.buffer.len() < MAX_LINE_LENGTH {
+                self.buffer.push(byte);
+            }
+        }
+    }
+
+    fn finish(&mut self, records: &mut Vec<Record>) {
+        let line = std::mem::take(&mut self.buffer);
+        self.push_line(&line, records);
+    }
+
+    fn summary(&self) -> String {
+        format!("SNR: {}", self.report)
+    }
+}
+
+/// Everything extracted from a complete SNR file.
+pub struct SnrLog {
+    pub records: Vec<Record>,
+    pub report: SnrReport,
+}
+
+/// Reads a complete gnssrefl SNR file covering the GPS day `day`.
+pub fn read_records<R: Read>(reader: R, config: &Config, day: i64) -> io::Result<SnrLog> {
+    let mut source = StreamSource::new(reader, SnrParser::new(config, day));
+    let records = source.read_all()?;
+    Ok(SnrLog {
+        records,
+        report: source.parser().report().clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(network: Network, band: Band, satellite: u32, time: Epoch, snr: f64) -> Record {
+        Record {
+            id: Record::make_id(network, band, satellite),
+            satellite,
+            elevation: 5.1234,
+            azimuth: 123.4567,
+            snr,
+            time,
+            network,
+            band,
+            quality: Quality::default(),
+        }
+    }
+
+    #[test]
+    fn write_read_round_trip() {
+        let day = days_from_civil(2024, 1, 1);
+        // 5 Hz epochs: the seconds of the day need their fraction.
+        let times: Vec<Epoch> = (0..3).map(|i| Epoch::from_gps_days(day, 43_200_000 + 200 * i)).collect();
+        let mut records = Vec::new();
+        for &time in &times {
+            records.push(record(Network::GPS, Band::L1, 5, time, 45.25));
+            records.push(record(Network::GPS, Band::L2, 5, time, 38.5));
+            records.push(record(Network::Galileo, Band::E1, 11, time, 41.0));
+        }
+
+        let mut file = Vec::new();
+        let report = write_records(&mut file, &records, SnrType::Snr50).unwrap();
+        assert_eq!(report.lines, 6);
+        assert!(String::from_utf8_lossy(&file).contains(" 43200.200 "));
+
+        let log = read_records(file.as_slice(), &Config::default(), day).unwrap();
+        assert_eq!(log.report.malformed_lines, 0);
+        let key = |r: &Record| (r.time, r.network as u32, r.satellite, r.band as u32);
+        let mut read = log.records.clone();
+        read.sort_by_key(key);
+        records.sort_by_key(key);
+        assert_eq!(read.len(), records.len());
+        for (a, b) in read.iter().zip(&records) {
+            assert_eq!(key(a), key(b));
+            assert_eq!((a.elevation, a.azimuth, a.snr), (b.elevation, b.azimuth, b.snr));
+        }
+    }
+}
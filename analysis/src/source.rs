@@ -0,0 +1,242 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::net::TcpStream;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::Config;
+use crate::db::record::Record;
+use crate::input;
+use crate::nmea::NmeaParser;
+use crate::rinex::RinexParser;
+use crate::rtcm::RtcmParser;
+use crate::snr::{self, SnrParser, SnrType};
+use crate::station::StationMetadata;
+use crate::stream::RecordParser;
+use crate::time::Epoch;
+use crate::ubx::UbxParser;
+
+/// Where the observations are read from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SourceKind {
+    /// A log file, possibly compressed.
+    File(String),
+    Stdin,
+    /// A TCP server streaming receiver output, as `host:port`.
+    Tcp(String),
+    /// A serial device such as `/dev/ttyAMA0`, read as a file. Baud rate and
+    /// framing must be set up beforehand (e.g. with `stty`).
+    Serial(String),
+}
+
+impl SourceKind {
+    /// `-` for stdin, `tcp://host:port`, `/dev/...` for a serial device, a file otherwise.
+    pub fn from_arg(arg: &str) -> Self {
+        if arg == "-" {
+            SourceKind::Stdin
+        } else if let Some(address) = arg.strip_prefix("tcp://") {
+            SourceKind::Tcp(address.to_string())
+        } else if arg.starts_with("/dev/") {
+            SourceKind::Serial(arg.to_string())
+        } else {
+            SourceKind::File(arg.to_string())
+        }
+    }
+}
+
+/// Data format of a source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    Nmea,
+    Ubx,
+    Rtcm,
+    Rinex,
+    Snr,
+}
+
+impl InputFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        let format = match name.to_ascii_lowercase().as_str() {
+            "nmea" => InputFormat::Nmea,
+            "ubx" => InputFormat::Ubx,
+            "rtcm" | "rtcm3" => InputFormat::Rtcm,
+            "rinex" => InputFormat::Rinex,
+            "snr" => InputFormat::Snr,
+            _ => return None,
+        };
+        Some(format)
+    }
+
+    /// Guesses the format from a file name without compression suffixes.
+    /// RINEX 3 long names end in `.rnx` (`.crx` when Hatanaka compressed),
+    /// RINEX 2 short names in `.yyo` (`.yyd`). Anything unknown is NMEA.
+    pub fn from_file_name(name: &str) -> Self {
+        let extension = name.rsplit_once('.').map_or("", |(_, ext)| ext);
+        if extension == "ubx" {
+            return InputFormat::Ubx;
+        }
+        if extension == "rtcm3" || extension == "rtcm" {
+            return InputFormat::Rtcm;
+        }
+        if SnrType::from_extension(extension).is_some() {
+            return InputFormat::Snr;
+        }
+        let ext = extension.as_bytes();
+        let rinex_2 = ext.len() == 3 && ext[..2].iter().all(u8::is_ascii_digit) && matches!(ext[2].to_ascii_lowercase(), b'o' | b'd');
+        if extension == "rnx" || extension == "crx" || rinex_2 {
+            return InputFormat::Rinex;
+        }
+        InputFormat::Nmea
+    }
+}
+
+/// Anything observations come from: a file, a socket, a serial port.
+/// Records are pulled in batches as they become available; a live source
+/// blocks until the receiver sends more.
+pub trait ObservationSource {
+    /// The next batch of records, possibly empty, or None once the source is exhausted.
+    fn next_batch(&mut self) -> io::Result<Option<Vec<Record>>>;
+
+    /// What the source has told about the station so far.
+    fn station(&self) -> StationMetadata;
+
+    /// One line on how the input was handled, for logging.
+    fn summary(&self) -> String;
+
+    /// Pushes every batch into `sink` until the source is exhausted.
+    fn run(&mut self, sink: &mut dyn FnMut(Vec<Record>, &StationMetadata)) -> io::Result<()> {
+        while let Some(batch) = self.next_batch()? {
+            if !batch.is_empty() {
+                let station = self.station();
+                sink(batch, &station);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A byte stream decoded by one of the record parsers: the parser chosen
+/// at run time, or a concrete one where its report is wanted afterwards.
+pub struct StreamSource<R: Read, P: RecordParser = Box<dyn RecordParser>> {
+    reader: R,
+    parser: P,
+    chunk: Vec<u8>,
+    done: bool,
+}
+
+impl<R: Read, P: RecordParser> StreamSource<R, P> {
+    pub fn new(reader: R, parser: P) -> Self {
+        StreamSource {
+            reader,
+            parser,
+            chunk: vec![0; 8192],
+            done: false,
+        }
+    }
+
+    pub fn parser(&self) -> &P {
+        &self.parser
+    }
+
+    /// Reads the stream to its end, e.g. a complete log file.
+    pub fn read_all(&mut self) -> io::Result<Vec<Record>> {
+        let mut records = Vec::new();
+        while let Some(batch) = self.next_batch()? {
+            records.extend(batch);
+        }
+        Ok(records)
+    }
+}
+
+impl<R: Read, P: RecordParser> ObservationSource for StreamSource<R, P> {
+    /// Reads until a chunk yields records, so a batch holds what one read
+    /// completed (typically one epoch from a live receiver).
+    fn next_batch(&mut self) -> io::Result<Option<Vec<Record>>> {
+        if self.done {
+            return Ok(None);
+        }
+        let mut records = Vec::new();
+        while records.is_empty() && self.parser.failure().is_none() {
+            match self.reader.read(&mut self.chunk) {
+                Ok(0) => {
+                    self.parser.finish(&mut records);
+                    self.done = true;
+                    break;
+                }
+                Ok(n) => self.parser.push_bytes(&self.chunk[..n], &mut records),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                // A serial port with a read timeout: hand back what there is.
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        // Records parsed before a failure go out first; the failure follows with the next call.
+        if records.is_empty() && let Some(failure) = self.parser.failure() {
+            self.done = true;
+            return Err(io::Error::new(io::ErrorKind::InvalidData, failure));
+        }
+        Ok(Some(records))
+    }
+
+    fn station(&self) -> StationMetadata {
+        self.parser.station()
+    }
+
+    fn summary(&self) -> String {
+        self.parser.summary()
+    }
+}
+
+fn now() -> Epoch {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    Epoch::from_unix_utc_millis(since_epoch.as_millis() as i64)
+}
+
+/// A parser for `format`. `file_name` dates SNR files, which only carry the
+/// time of day; `reference` dates RTCM streams, which only carry the time of
+/// week.
+pub fn parser_for(format: InputFormat, config: &Config, file_name: Option<&str>, reference: Epoch) -> io::Result<Box<dyn RecordParser>> {
+    let parser: Box<dyn RecordParser> = match format {
+        InputFormat::Nmea => Box::new(NmeaParser::new(config)),
+        InputFormat::Ubx => Box::new(UbxParser::new(config)),
+        InputFormat::Rtcm => Box::new(RtcmParser::new(config, reference)),
+        InputFormat::Rinex => Box::new(RinexParser::new(config)),
+        InputFormat::Snr => {
+            let day = file_name.and_then(snr::day_from_file_name).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "SNR input needs a file named ssssddd0.yy.snrNN")
+            })?;
+            Box::new(SnrParser::new(config, day))
+        }
+    };
+    Ok(parser)
+}
+
+/// Opens the source selected in `config`. Without an explicit format, a file
+/// name tells the format and anything else is taken to be NMEA.
+pub fn open(config: &Config) -> io::Result<Box<dyn ObservationSource>> {
+    match &config.source {
+        SourceKind::File(path) => {
+            let name = input::content_name(path);
+            let format = config.input_format.unwrap_or_else(|| InputFormat::from_file_name(name));
+            // A logged RTCM stream is dated relative to when the file was last written.
+            let reference = std::fs::metadata(path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or_else(now, |d| Epoch::from_unix_utc_millis(d.as_millis() as i64));
+            let parser = parser_for(format, config, Some(name), reference)?;
+            Ok(Box::new(StreamSource::new(input::open(path)?, parser)))
+        }
+        SourceKind::Stdin => {
+            let parser = parser_for(config.input_format.unwrap_or(InputFormat::Nmea), config, None, now())?;
+            Ok(Box::new(StreamSource::new(io::stdin(), parser)))
+        }
+        SourceKind::Tcp(address) => {
+            let parser = parser_for(config.input_format.unwrap_or(InputFormat::Nmea), config, None, now())?;
+            Ok(Box::new(StreamSource::new(TcpStream::connect(address)?, parser)))
+        }
+        SourceKind::Serial(path) => {
+            let parser = parser_for(config.input_format.unwrap_or(InputFormat::Nmea), config, None, now())?;
+            Ok(Box::new(StreamSource::new(File::open(path)?, parser)))
+        }
+    }
+}
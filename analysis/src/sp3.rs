@@ -0,0 +1,206 @@
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read};
+
+use crate::orbit::precise::PreciseOrbits;
+use crate::rinex::{column, normalise_satellite, TimeSystem};
+use crate::time::Epoch;
+
+/// TAI is ahead of GPS time by a constant 19 s.
+const TAI_OFFSET: f64 = 19.0;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("SP3: {}", message))
+}
+
+/// Counts of how the records of an SP3 file were handled.
+#[derive(Debug, Clone, Default)]
+pub struct Sp3Report {
+    pub epochs: usize,
+    pub positions: usize,
+    /// Positions given as zero, the SP3 marker for a missing value.
+    pub missing_positions: usize,
+    pub unknown_satellites: usize,
+    pub malformed_lines: usize,
+}
+
+impl fmt::Display for Sp3Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} epochs, {} positions, {} missing positions, {} unknown satellites, {} malformed lines",
+            self.epochs, self.positions, self.missing_positions, self.unknown_satellites, self.malformed_lines,
+        )
+    }
+}
+
+pub struct Sp3Log {
+    pub orbits: PreciseOrbits,
+    pub report: Sp3Report,
+    /// Version letter, `c` or `d`.
+    pub version: char,
+}
+
+/// Calendar epoch of an epoch line, from column `start` on.
+fn parse_calendar(line: &str, start: usize) -> Option<(i32, u32, u32, u32, u32, f64)> {
+    let year = column(line, start, start + 4).parse().ok()?;
+    let month = column(line, start + 5, start + 7).parse().ok()?;
+    let day = column(line, start + 8, start + 10).parse().ok()?;
+    let hour = column(line, start + 11, start + 13).parse().ok()?;
+    let minute = column(line, start + 14, start + 16).parse().ok()?;
+    let seconds = column(line, start + 17, start + 28).parse().ok()?;
+    Some((year, month, day, hour, minute, seconds))
+}
+
+/// Reads the positions of an SP3-c or SP3-d orbit file, in metres on the
+/// GPS time scale. Velocities and correlation records are ignored.
+pub fn read_sp3<R: Read>(reader: R) -> io::Result<Sp3Log> {
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+
+    if reader.read_line(&mut line)? == 0 || !line.starts_with('#') {
+        return Err(invalid("missing # header line"));
+    }
+    let version = line[1..].chars().next().unwrap_or(' ');
+    if !matches!(version, 'c' | 'd') {
+        return Err(invalid("only SP3-c and SP3-d are supported"));
+    }
+
+    let mut report = Sp3Report::default();
+    let mut orbits = PreciseOrbits::new(0.0);
+    let mut time_system: Option<String> = None;
+    let mut epoch: Option<Epoch> = None;
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let text = line.trim_end();
+
+        if text.starts_with("##") {
+            let interval = column(text, 24, 38).parse::<f64>().map_err(|_| invalid("malformed ## line"))?;
+            orbits = PreciseOrbits::new(interval);
+        } else if text.starts_with("%c") {
+            // Only the first %c line carries the time system.
+            if time_system.is_none() {
+                time_system = Some(column(text, 9, 12).to_string());
+            }
+        } else if text.starts_with('*') {
+            let Some((year, month, day, hour, minute, seconds)) = parse_calendar(text, 3) else {
+                report.malformed_lines += 1;
+                epoch = None;
+                continue;
+            };
+            epoch = Some(match time_system.as_deref().unwrap_or("GPS") {
+                "TAI" => TimeSystem::Gps.epoch(year, month, day, hour, minute, seconds) - TAI_OFFSET,
+                code => TimeSystem::from_code(code)
+                    .ok_or_else(|| invalid("unsupported time system"))?
+                    .epoch(year, month, day, hour, minute, seconds),
+            });
+            report.epochs += 1;
+        } else if let Some(record) = text.strip_prefix('P') {
+            let Some(time) = epoch else {
+                report.malformed_lines += 1;
+                continue;
+            };
+            let system = record.chars().next().unwrap_or(' ');
+            let Some(prn) = column(text, 2, 4).parse::<u32>().ok() else {
+                report.malformed_lines += 1;
+                continue;
+            };
+            let Some((network, satellite)) = normalise_satellite(system, prn) else {
+                report.unknown_satellites += 1;
+                continue;
+            };
+            let coordinate = |i: usize| column(text, 4 + 14 * i, 18 + 14 * i).parse::<f64>().ok();
+            let (Some(x), Some(y), Some(z)) = (coordinate(0), coordinate(1), coordinate(2)) else {
+                report.malformed_lines += 1;
+                continue;
+            };
+            if x == 0.0 && y == 0.0 && z == 0.0 {
+                report.missing_positions += 1;
+                continue;
+            }
+            orbits.push(network, satellite, time, [x * 1e3, y * 1e3, z * 1e3]);
+            report.positions += 1;
+        } else if text.starts_with("EOF") {
+            break;
+        }
+    }
+
+    if orbits.interval() == 0.0 {
+        return Err(invalid("missing ## header line"));
+    }
+    orbits.sort();
+    Ok(Sp3Log { orbits, report, version })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::record::Network;
+    use crate::orbit::GeometrySource;
+
+    const RADIUS: f64 = 26_560_000.0;
+    /// Angular rate (rad/s) of a GPS orbit, half a sidereal day.
+    const RATE: f64 = 2.0 * std::f64::consts::PI / 43_082.0;
+
+    fn orbit_position(seconds: f64) -> (f64, f64, f64) {
+        let angle = RATE * seconds;
+        (RADIUS * angle.cos(), RADIUS * angle.sin() * 0.6, RADIUS * angle.sin() * 0.8)
+    }
+
+    /// Twelve 15 minute epochs from 2024-01-01 00:00 GPS time: G01 on a
+    /// circular orbit, G02 always missing.
+    fn sp3_file() -> String {
+        let mut text = String::from("#dP2024  1  1  0  0  0.00000000      12 ORBIT IGS20 HLM  IGS\n");
+        text += &format!("## {:4} {:15.8} {:14.8} {:5} {:15.13}\n", 2295, 86_400.0, 900.0, 60_310, 0.0);
+        text += "%c M  cc GPS ccc cccc cccc cccc cccc ccccc ccccc ccccc ccccc\n";
+        for k in 0..12 {
+            let minutes = 15 * k;
+            text += &format!("*  {:4} {:2} {:2} {:2} {:2} {:11.8}\n", 2024, 1, 1, minutes / 60, minutes % 60, 0.0);
+            let (x, y, z) = orbit_position(900.0 * k as f64);
+            text += &format!("PG01{:14.6}{:14.6}{:14.6}{:14.6}\n", x / 1e3, y / 1e3, z / 1e3, 12.345678);
+            text += &format!("PG02{:14.6}{:14.6}{:14.6}{:14.6}\n", 0.0, 0.0, 0.0, 999_999.999_999);
+        }
+        text += "EOF\n";
+        text
+    }
+
+    #[test]
+    fn positions_are_read_and_interpolated() {
+        let log = read_sp3(sp3_file().as_bytes()).unwrap();
+        assert_eq!(log.version, 'd');
+        assert_eq!((log.report.epochs, log.report.positions, log.report.missing_positions), (12, 12, 12));
+        assert_eq!(log.orbits.interval(), 900.0);
+
+        let start = TimeSystem::Gps.epoch(2024, 1, 1, 0, 0, 0.0);
+        // Halfway between two samples, in the middle of the file.
+        let seconds = 5.5 * 900.0;
+        let (x, y, z) = log.orbits.satellite_position(Network::GPS, 1, start + seconds).unwrap();
+        let expected = orbit_position(seconds);
+        assert!((x - expected.0).abs() < 0.01 && (y - expected.1).abs() < 0.01 && (z - expected.2).abs() < 0.01);
+
+        assert!(log.orbits.satellite_position(Network::GPS, 1, start - 1.0).is_none());
+        assert!(log.orbits.satellite_position(Network::GPS, 2, start + seconds).is_none());
+    }
+
+    #[test]
+    fn interpolation_does_not_span_gaps() {
+        // Dropping one epoch leaves a 30 minute gap inside every window.
+        let mut skip = false;
+        let mut filtered = String::new();
+        for line in sp3_file().split_inclusive('\n') {
+            if line.starts_with('*') {
+                skip = line.starts_with("*  2024  1  1  1 15");
+            }
+            if !skip {
+                filtered += line;
+            }
+        }
+        let log = read_sp3(filtered.as_bytes()).unwrap();
+        assert_eq!(log.report.epochs, 11);
+        let start = TimeSystem::Gps.epoch(2024, 1, 1, 0, 0, 0.0);
+        assert!(log.orbits.satellite_position(Network::GPS, 1, start + 5.5 * 900.0).is_none());
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::refraction::Weather;
+
+/// WGS84 semi-major axis (m) and flattening.
+const WGS84_A: f64 = 6_378_137.0;
+const WGS84_F: f64 = 1.0 / 298.257_223_563;
+
+/// Most recent fixes kept for averaging, one day at 1 Hz.
+const MAX_POSITION_SAMPLES: usize = 86_400;
+
+/// Median absolute deviations beyond which a fix counts as an outlier.
+const OUTLIER_MADS: f64 = 3.0;
+
+/// Outlier threshold floor (m), so a very stable receiver does not reject
+/// everything beyond its last millimetre.
+const MIN_OUTLIER_DISTANCE: f64 = 0.05;
+
+/// Fix quality indicator from GGA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixQuality {
+    Invalid,
+    Gps,
+    Differential,
+    Pps,
+    RtkFixed,
+    RtkFloat,
+    DeadReckoning,
+    Manual,
+    Simulation,
+}
+
+impl FixQuality {
+    pub fn from_gga(value: u32) -> Option<Self> {
+        let quality = match value {
+            0 => FixQuality::Invalid,
+            1 => FixQuality::Gps,
+            2 => FixQuality::Differential,
+            3 => FixQuality::Pps,
+            4 => FixQuality::RtkFixed,
+            5 => FixQuality::RtkFloat,
+            6 => FixQuality::DeadReckoning,
+            7 => FixQuality::Manual,
+            8 => FixQuality::Simulation,
+            _ => return None,
+        };
+        Some(quality)
+    }
+
+    /// Whether the receiver actually computed this position from the satellites.
+    pub fn is_measured(&self) -> bool {
+        matches!(
+            self,
+            FixQuality::Gps | FixQuality::Differential | FixQuality::Pps | FixQuality::RtkFixed | FixQuality::RtkFloat
+        )
+    }
+}
+
+/// A single receiver position as reported in one epoch.
+#[derive(Debug, Clone, Copy)]
+pub struct PositionFix {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Height above mean sea level (m).
+    pub orthometric_height: f64,
+    /// Geoid separation, ellipsoid minus mean sea level (m).
+    pub geoid_separation: f64,
+    pub quality: FixQuality,
+    pub num_satellites: u32,
+    pub hdop: f64,
+}
+
+impl PositionFix {
+    pub fn ellipsoidal_height(&self) -> f64 {
+        self.orthometric_height + self.geoid_separation
+    }
+}
+
+/// Averaged receiver position of the station.
+#[derive(Debug, Clone, Copy)]
+pub struct StationPosition {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub ellipsoidal_height: f64,
+    pub orthometric_height: Option<f64>,
+    /// Standard deviations of the accepted fixes (m).
+    pub std_north: f64,
+    pub std_east: f64,
+    pub std_up: f64,
+    pub samples_used: usize,
+    pub samples_rejected: usize,
+}
+
+impl StationPosition {
+    /// A position taken as is from Earth-centred, Earth-fixed WGS84
+    /// coordinates (m), e.g. the approximate position in a RINEX header.
+    pub fn from_ecef(x: f64, y: f64, z: f64) -> Self {
+        let (latitude, longitude, ellipsoidal_height) = geodetic_from_ecef(x, y, z);
+        StationPosition {
+            latitude,
+            longitude,
+            ellipsoidal_height,
+            orthometric_height: None,
+            std_north: 0.0,
+            std_east: 0.0,
+            std_up: 0.0,
+            samples_used: 0,
+            samples_rejected: 0,
+        }
+    }
+
+    /// Earth-centred, Earth-fixed WGS84 coordinates (m).
+    pub fn ecef(&self) -> (f64, f64, f64) {
+        ecef_from_geodetic(self.latitude, self.longitude, self.ellipsoidal_height)
+    }
+}
+
+impl fmt::Display for StationPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.samples_used == 0 {
+            return write!(f, "lat {:.8}, lon {:.8}, h {:.3} m (not averaged)", self.latitude, self.longitude, self.ellipsoidal_height);
+        }
+        write!(
+            f,
+            "lat {:.8}, lon {:.8}, h {:.3} m (std n/e/u {:.3}/{:.3}/{:.3} m, {} fixes used, {} rejected)",
+            self.latitude,
+            self.longitude,
+            self.ellipsoidal_height,
+            self.std_north,
+            self.std_east,
+            self.std_up,
+            self.samples_used,
+            self.samples_rejected,
+        )
+    }
+}
+
+/// What is known about the receiving station, shared by all later processing
+/// steps (geometry, datum of the reflector height, Fresnel zones).
+#[derive(Debug, Clone, Default)]
+pub struct StationMetadata {
+    pub marker_name: Option<String>,
+    pub position: Option<StationPosition>,
+    /// Height of the antenna reference point above the marker (m).
+    pub antenna_height: Option<f64>,
+    /// Nominal observation interval (s).
+    pub interval: Option<f64>,
+    /// Latest surface pressure and temperature, where a sensor provides them.
+    pub weather: Option<Weather>,
+}
+
+/// Collects position fixes and turns them into one robust station position.
+pub struct PositionAverager {
+    max_hdop: f64,
+    fixes: VecDeque<PositionFix>,
+    skipped: usize,
+}
+
+impl PositionAverager {
+    pub fn new(max_hdop: f64) -> Self {
+        PositionAverager {
+            max_hdop,
+            fixes: VecDeque::new(),
+            skipped: 0,
+        }
+    }
+
+    pub fn push(&mut self, fix: PositionFix) {
+        if !fix.quality.is_measured() || fix.hdop > self.max_hdop {
+            self.skipped += 1;
+            return;
+        }
+        if self.fixes.len() == MAX_POSITION_SAMPLES {
+            self.fixes.pop_front();
+        }
+        self.fixes.push_back(fix);
+    }
+
+    /// Median position, then the mean of all fixes within a few median absolute
+    /// deviations of it.
+    pub fn average(&self) -> Option<StationPosition> {
+        if self.fixes.is_empty() {
+            return None;
+        }
+
+        let lat0 = median(self.fixes.iter().map(|f| f.latitude).collect());
+        let lon0 = median(self.fixes.iter().map(|f| f.longitude).collect());
+        let h0 = median(self.fixes.iter().map(|f| f.ellipsoidal_height()).collect());
+
+        let (m_north, m_east) = meters_per_degree(lat0);
+        let offsets: Vec<(f64, f64, f64)> = self.fixes.iter()
+            .map(|f| ((f.latitude - lat0) * m_north, (f.longitude - lon0) * m_east, f.ellipsoidal_height() - h0))
+            .collect();
+
+        let horizontal: Vec<f64> = offsets.iter().map(|(n, e, _)| n.hypot(*e)).collect();
+        let vertical: Vec<f64> = offsets.iter().map(|(_, _, u)| u.abs()).collect();
+        let max_horizontal = (OUTLIER_MADS * 1.4826 * median(horizontal.clone())).max(MIN_OUTLIER_DISTANCE);
+        let max_vertical = (OUTLIER_MADS * 1.4826 * median(vertical.clone())).max(MIN_OUTLIER_DISTANCE);
+
+        let inliers: Vec<usize> = (0..offsets.len())
+            .filter(|&i| horizontal[i] <= max_horizontal && vertical[i] <= max_vertical)
+            .collect();
+
+        let (mean_north, std_north) = mean_std(inliers.iter().map(|&i| offsets[i].0).collect());
+        let (mean_east, std_east) = mean_std(inliers.iter().map(|&i| offsets[i].1).collect());
+        let (mean_up, std_up) = mean_std(inliers.iter().map(|&i| offsets[i].2).collect());
+        let (mean_separation, _) = mean_std(inliers.iter().map(|&i| self.fixes[i].geoid_separation).collect());
+
+        Some(StationPosition {
+            latitude: lat0 + mean_north / m_north,
+            longitude: lon0 + mean_east / m_east,
+            ellipsoidal_height: h0 + mean_up,
+            orthometric_height: Some(h0 + mean_up - mean_separation),
+            std_north,
+            std_east,
+            std_up,
+            samples_used: inliers.len(),
+            samples_rejected: self.skipped + offsets.len() - inliers.len(),
+        })
+    }
+}
+
+fn mean_std(values: Vec<f64>) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let var = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+    (mean, var.sqrt())
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+/// WGS84 ECEF position (m) of a geodetic latitude, longitude (degrees) and
+/// ellipsoidal height (m).
+pub fn ecef_from_geodetic(latitude: f64, longitude: f64, height: f64) -> (f64, f64, f64) {
+    let e2 = WGS84_F * (2.0 - WGS84_F);
+    let (phi, lambda) = (latitude.to_radians(), longitude.to_radians());
+    let n = WGS84_A / (1.0 - e2 * phi.sin().powi(2)).sqrt();
+    (
+        (n + height) * phi.cos() * lambda.cos(),
+        (n + height) * phi.cos() * lambda.sin(),
+        (n * (1.0 - e2) + height) * phi.sin(),
+    )
+}
+
+/// Geodetic latitude, longitude (degrees) and ellipsoidal height (m) of a
+/// WGS84 ECEF position, by fixed-point iteration on the latitude.
+pub fn geodetic_from_ecef(x: f64, y: f64, z: f64) -> (f64, f64, f64) {
+    let e2 = WGS84_F * (2.0 - WGS84_F);
+    let p = x.hypot(y);
+    let longitude = y.atan2(x);
+
+    let mut latitude = z.atan2(p * (1.0 - e2));
+    let mut height = 0.0;
+    for _ in 0..10 {
+        let n = WGS84_A / (1.0 - e2 * latitude.sin().powi(2)).sqrt();
+        height = p / latitude.cos() - n;
+        latitude = z.atan2(p * (1.0 - e2 * n / (n + height)));
+    }
+
+    (latitude.to_degrees(), longitude.to_degrees(), height)
+}
+
+/// Length of one degree of latitude and of longitude (m) at a latitude.
+fn meters_per_degree(latitude: f64) -> (f64, f64) {
+    let phi = latitude.to_radians();
+    let e2 = WGS84_F * (2.0 - WGS84_F);
+    let w = (1.0 - e2 * phi.sin().powi(2)).sqrt();
+    let meridional = WGS84_A * (1.0 - e2) / w.powi(3);
+    let normal = WGS84_A / w;
+    (meridional.to_radians(), (normal * phi.cos()).to_radians())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ecef_known_points() {
+        let (x, y, z) = ecef_from_geodetic(0.0, 0.0, 0.0);
+        assert!((x - WGS84_A).abs() < 1e-6 && y.abs() < 1e-6 && z.abs() < 1e-6);
+        // The pole lies one semi-minor axis, a(1 - f), from the centre.
+        let (_, _, z) = ecef_from_geodetic(90.0, 0.0, 0.0);
+        assert!((z - 6_356_752.314_245).abs() < 1e-3);
+
+        let (lat, lon, h) = geodetic_from_ecef(3_924_687.701, 301_132.765, 5_001_910.833);
+        let (x, y, z) = ecef_from_geodetic(lat, lon, h);
+        assert!((x - 3_924_687.701).abs() < 1e-4 && (y - 301_132.765).abs() < 1e-4 && (z - 5_001_910.833).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,48 @@
+use crate::db::record::Record;
+use crate::station::StationMetadata;
+
+/// A parser that turns a byte stream, fed in arbitrary chunks (e.g. straight
+/// from a UART read), into records.
+pub trait RecordParser {
+    fn push_bytes(&mut self, chunk: &[u8], records: &mut Vec<Record>);
+
+    /// Called once at the end of the stream to hand out anything still pending.
+    fn finish(&mut self, records: &mut Vec<Record>);
+
+    /// What the stream has told about the station so far.
+    fn station(&self) -> StationMetadata {
+        StationMetadata::default()
+    }
+
+    /// One line on how the stream was handled, for logging.
+    fn summary(&self) -> String;
+
+    /// Why the stream cannot be parsed any further, if it cannot.
+    fn failure(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Lets a parser chosen at run time (e.g. by [`crate::source::parser_for`])
+/// drive a [`crate::source::StreamSource`].
+impl<P: RecordParser + ?Sized> RecordParser for Box<P> {
+    fn push_bytes(&mut self, chunk: &[u8], records: &mut Vec<Record>) {
+        (**self).push_bytes(chunk, records)
+    }
+
+    fn finish(&mut self, records: &mut Vec<Record>) {
+        (**self).finish(records)
+    }
+
+    fn station(&self) -> StationMetadata {
+        (**self).station()
+    }
+
+    fn summary(&self) -> String {
+        (**self).summary()
+    }
+
+    fn failure(&self) -> Option<String> {
+        (**self).failure()
+    }
+}
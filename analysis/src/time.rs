@@ -0,0 +1,191 @@
+use std::fmt;
+use std::ops::{Add, Sub};
+
+/// 1980-01-06T00:00:00 UTC as Unix time.
+const GPS_EPOCH_UNIX: i64 = 315_964_800;
+const SECONDS_PER_DAY: i64 = 86_400;
+const MILLIS_PER_SECOND: i64 = 1000;
+pub const MILLIS_PER_DAY: i64 = SECONDS_PER_DAY * MILLIS_PER_SECOND;
+
+/// Unix time at which GPS-UTC changed, and the offset from then on.
+const LEAP_SECONDS: [(i64, i64); 18] = [
+    (362_793_600, 1),    // 1981-07-01
+    (394_329_600, 2),    // 1982-07-01
+    (425_865_600, 3),    // 1983-07-01
+    (489_024_000, 4),    // 1985-07-01
+    (567_993_600, 5),    // 1988-01-01
+    (631_152_000, 6),    // 1990-01-01
+    (662_688_000, 7),    // 1991-01-01
+    (709_948_800, 8),    // 1992-07-01
+    (741_484_800, 9),    // 1993-07-01
+    (773_020_800, 10),   // 1994-07-01
+    (820_454_400, 11),   // 1996-01-01
+    (867_715_200, 12),   // 1997-07-01
+    (915_148_800, 13),   // 1999-01-01
+    (1_136_073_600, 14), // 2006-01-01
+    (1_230_768_000, 15), // 2009-01-01
+    (1_341_100_800, 16), // 2012-07-01
+    (1_435_708_800, 17), // 2015-07-01
+    (1_483_228_800, 18), // 2017-01-01
+];
+
+/// GPS-UTC in seconds at the given Unix (UTC) time.
+fn leap_seconds_at_unix(unix: i64) -> i64 {
+    LEAP_SECONDS.iter().rev()
+        .find(|&&(since, _)| unix >= since)
+        .map(|&(_, leap)| leap)
+        .unwrap_or(0)
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date.
+pub fn days_from_civil(year: i32, month: u32, day: u32) -> i64 {
+    let y = year as i64 - if month <= 2 { 1 } else { 0 };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Inverse of [`days_from_civil`].
+pub fn civil_from_days(days: i64) -> (i32, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = (yoe + era * 400 + if month <= 2 { 1 } else { 0 }) as i32;
+    (year, month, day)
+}
+
+/// An absolute point in time with millisecond resolution, kept on the
+/// continuous GPS time scale so that differences are not disturbed by
+/// midnight or leap seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Epoch {
+    gps_millis: i64,
+}
+
+impl Epoch {
+    pub fn from_gps_week_millis(week: u32, tow_millis: i64) -> Self {
+        Epoch { gps_millis: week as i64 * 7 * MILLIS_PER_DAY + tow_millis }
+    }
+
+    pub fn from_unix_utc_millis(unix_millis: i64) -> Self {
+        let leap = leap_seconds_at_unix(unix_millis.div_euclid(MILLIS_PER_SECOND));
+        Epoch { gps_millis: unix_millis + (leap - GPS_EPOCH_UNIX) * MILLIS_PER_SECOND }
+    }
+
+    /// `days` since 1970-01-01 and milliseconds into that day, both in UTC.
+    pub fn from_utc_days(days: i64, millis_of_day: i64) -> Self {
+        Epoch::from_unix_utc_millis(days * MILLIS_PER_DAY + millis_of_day)
+    }
+
+    /// `days` since 1970-01-01 and milliseconds into that day, both read on
+    /// the GPS time scale rather than in UTC (e.g. RINEX epochs).
+    pub fn from_gps_days(days: i64, millis_of_day: i64) -> Self {
+        Epoch { gps_millis: days * MILLIS_PER_DAY + millis_of_day - GPS_EPOCH_UNIX * MILLIS_PER_SECOND }
+    }
+
+    /// Seconds since the GPS epoch (1980-01-06), without leap seconds.
+    pub fn gps_seconds(&self) -> f64 {
+        self.gps_millis as f64 / MILLIS_PER_SECOND as f64
+    }
+
+    /// Milliseconds into the day on the GPS time scale.
+    pub fn gps_millis_of_day(&self) -> i64 {
+        self.gps_millis.rem_euclid(MILLIS_PER_DAY)
+    }
+
+    /// GPS-UTC in seconds at this epoch.
+    pub fn leap_seconds(&self) -> i64 {
+        let approx = self.gps_millis.div_euclid(MILLIS_PER_SECOND) + GPS_EPOCH_UNIX;
+        leap_seconds_at_unix(approx - leap_seconds_at_unix(approx))
+    }
+
+    pub fn unix_utc_millis(&self) -> i64 {
+        self.gps_millis + (GPS_EPOCH_UNIX - self.leap_seconds()) * MILLIS_PER_SECOND
+    }
+
+    pub fn utc_date(&self) -> (i32, u32, u32) {
+        civil_from_days(self.unix_utc_millis().div_euclid(MILLIS_PER_DAY))
+    }
+
+    pub fn utc_millis_of_day(&self) -> i64 {
+        self.unix_utc_millis().rem_euclid(MILLIS_PER_DAY)
+    }
+}
+
+impl Add<f64> for Epoch {
+    type Output = Epoch;
+
+    /// The epoch a number of seconds later, rounded to the millisecond.
+    fn add(self, seconds: f64) -> Epoch {
+        Epoch { gps_millis: self.gps_millis + (seconds * MILLIS_PER_SECOND as f64).round() as i64 }
+    }
+}
+
+impl Sub<f64> for Epoch {
+    type Output = Epoch;
+
+    /// The epoch a number of seconds earlier, rounded to the millisecond.
+    fn sub(self, seconds: f64) -> Epoch {
+        self + (-seconds)
+    }
+}
+
+impl Sub for Epoch {
+    type Output = f64;
+
+    /// Elapsed seconds between two epochs.
+    fn sub(self, rhs: Epoch) -> f64 {
+        (self.gps_millis - rhs.gps_millis) as f64 / MILLIS_PER_SECOND as f64
+    }
+}
+
+impl fmt::Display for Epoch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (year, month, day) = self.utc_date();
+        let ms = self.utc_millis_of_day();
+        let sod = ms / MILLIS_PER_SECOND;
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            year, month, day, sod / 3600, (sod / 60) % 60, sod % 60, ms % MILLIS_PER_SECOND
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leap_second_at_end_of_2016() {
+        let before = Epoch::from_utc_days(days_from_civil(2016, 12, 31), 86_399_000);
+        let after = Epoch::from_utc_days(days_from_civil(2017, 1, 1), 0);
+        // 23:59:59 to 00:00:00 took two seconds: 23:59:60 was inserted.
+        assert_eq!(after - before, 2.0);
+        assert_eq!(before.leap_seconds(), 17);
+        assert_eq!(after.leap_seconds(), 18);
+        // GPS week 1930 started at 2017-01-01 00:00:00 GPS, 18 s before midnight UTC.
+        assert_eq!(after.gps_seconds(), (1930 * 7 * 86_400 + 18) as f64);
+        assert_eq!(after.to_string(), "2017-01-01T00:00:00.000Z");
+    }
+
+    #[test]
+    fn sub_second_epochs_round_trip() {
+        let day = days_from_civil(2025, 9, 16);
+        let epoch = Epoch::from_utc_days(day, 43_200_100);
+        assert_eq!(epoch.utc_millis_of_day(), 43_200_100);
+        assert_eq!(epoch.utc_date(), (2025, 9, 16));
+        let later = Epoch::from_utc_days(day, 43_200_200);
+        assert_eq!(later - epoch, 0.1);
+        assert_eq!(later.to_string(), "2025-09-16T12:00:00.200Z");
+        assert_eq!(Epoch::from_unix_utc_millis(later.unix_utc_millis()), later);
+    }
+}
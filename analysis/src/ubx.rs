@@ -0,0 +1,505 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read};
+
+use crate::config::Config;
+use crate::db::record::{self, Band, Network, Quality, Record};
+use crate::source::StreamSource;
+use crate::stream::RecordParser;
+use crate::time::Epoch;
+
+const SYNC_1: u8 = 0xB5;
+const SYNC_2: u8 = 0x62;
+const HEADER_LENGTH: usize = 6;
+const CHECKSUM_LENGTH: usize = 2;
+
+/// Largest payload accepted; a longer length field means we synced on noise.
+const MAX_PAYLOAD_LENGTH: usize = 8192;
+
+/// Geometry from NAV-SAT older than this (ms) is not used for new records.
+const MAX_GEOMETRY_AGE: i64 = 2000;
+
+const CLASS_NAV: u8 = 0x01;
+const CLASS_RXM: u8 = 0x02;
+const NAV_TIMEGPS: u8 = 0x20;
+const NAV_SAT: u8 = 0x35;
+const NAV_SIG: u8 = 0x43;
+const RXM_RAWX: u8 = 0x15;
+
+/// Which UBX message the C/N0 of the records is taken from. Both carry it per
+/// signal, so using both would duplicate every observation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CnoSource {
+    NavSig,
+    RawX,
+}
+
+/// Counts of how the frames of a UBX stream were handled.
+#[derive(Debug, Clone, Default)]
+pub struct UbxReport {
+    pub frames: usize,
+    pub bad_checksum: usize,
+    pub skipped_bytes: usize,
+    pub truncated: usize,
+    pub ignored: usize,
+    pub nav_sat: usize,
+    pub nav_sig: usize,
+    pub rawx: usize,
+    pub undated: usize,
+    /// Signals dropped because another signal of the same band came first.
+    pub duplicate_signals: usize,
+}
+
+impl fmt::Display for UbxReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} frames (NAV-SAT: {}, NAV-SIG: {}, RXM-RAWX: {}, ignored: {}), {} bad checksums, {} truncated, {} bytes skipped while syncing, {} messages before the GPS week was known, {} duplicate signals",
+            self.frames,
+            self.nav_sat,
+            self.nav_sig,
+            self.rawx,
+            self.ignored,
+            self.bad_checksum,
+            self.truncated,
+            self.skipped_bytes,
+            self.undated,
+            self.duplicate_signals,
+        )
+    }
+}
+
+fn gnss_to_network(gnss_id: u8) -> Network {
+    match gnss_id {
+        0 => Network::GPS,
+        1 => Network::SBAS,
+        2 => Network::Galileo,
+        3 => Network::BeiDou,
+        5 => Network::QZSS,
+        6 => Network::GLONASS,
+        7 => Network::NavIC,
+        _ => Network::Unknown,
+    }
+}
+
+/// Maps a u-blox signal id (per gnssId) to the carrier it is transmitted on.
+fn signal_to_band(network: Network, sig_id: u8) -> Band {
+    match (network, sig_id) {
+        (Network::GPS, 0) => Band::L1,
+        (Network::GPS, 3..=4) => Band::L2,
+        (Network::GPS, 6..=7) => Band::L5,
+        (Network::SBAS, 0) => Band::L1,
+        (Network::Galileo, 0..=1) => Band::E1,
+        (Network::Galileo, 3..=4) => Band::E5a,
+        (Network::Galileo, 5..=6) => Band::E5b,
+        (Network::Galileo, 8..=10) => Band::E6,
+        (Network::BeiDou, 0..=1) => Band::B1I,
+        (Network::BeiDou, 2..=3) => Band::B2b,
+        (Network::BeiDou, 4 | 10) => Band::B3,
+        (Network::BeiDou, 5..=6) => Band::B1C,
+        (Network::BeiDou, 7..=8) => Band::B2a,
+        (Network::QZSS, 0..=1 | 12) => Band::L1,
+        (Network::QZSS, 4..=5) => Band::L2,
+        (Network::QZSS, 8..=9) => Band::L5,
+        (Network::GLONASS, 0) => Band::G1,
+        (Network::GLONASS, 2) => Band::G2,
+        (Network::NavIC, 0) => Band::L5,
+        _ => Band::Unknown,
+    }
+}
+
+/// Fletcher-8 checksum over class, id, length and payload.
+fn checksum(data: &[u8]) -> (u8, u8) {
+    data.iter().fold((0u8, 0u8), |(a, b), &byte| {
+        let a = a.wrapping_add(byte);
+        (a, b.wrapping_add(a))
+    })
+}
+
+fn u16_le(p: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([p[at], p[at + 1]])
+}
+
+fn u32_le(p: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([p[at], p[at + 1], p[at + 2], p[at + 3]])
+}
+
+fn f64_le(p: &[u8], at: usize) -> f64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&p[at..at + 8]);
+    f64::from_le_bytes(bytes)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SatGeometry {
+    elevation: f64,
+    azimuth: f64,
+    in_use: bool,
+    healthy: bool,
+}
+
+/// Incremental UBX decoder. Frames are located by their sync characters and
+/// checked against their checksum; after a bad frame the decoder resyncs on
+/// the next sync pair, so interleaved NMEA text or line noise is skipped.
+pub struct UbxParser {
+    config: Config,
+    buffer: Vec<u8>,
+    week: Option<u32>,
+    geometry: HashMap<(Network, u32), SatGeometry>,
+    geometry_time: Option<Epoch>,
+    report: UbxReport,
+}
+
+impl UbxParser {
+    pub fn new(config: &Config) -> Self {
+        UbxParser {
+            config: config.clone(),
+            buffer: Vec::new(),
+            week: None,
+            geometry: HashMap::new(),
+            geometry_time: None,
+            report: UbxReport::default(),
+        }
+    }
+
+    pub fn report(&self) -> &UbxReport {
+        &self.report
+    }
+
+    /// Extracts all complete frames from the buffer, keeping a trailing partial one.
+    fn drain_frames(&mut self, records: &mut Vec<Record>) {
+        let mut start = 0;
+        loop {
+            let rest = &self.buffer[start..];
+            let sync = match rest.windows(2).position(|w| w == [SYNC_1, SYNC_2]) {
+                Some(i) => i,
+                None => {
+                    // Keep a trailing first sync byte, its partner may be in the next chunk.
+                    let keep = usize::from(rest.last() == Some(&SYNC_1));
+                    self.report.skipped_bytes += rest.len() - keep;
+                    start = self.buffer.len() - keep;
+                    break;
+                }
+            };
+            self.report.skipped_bytes += sync;
+            start += sync;
+
+            let rest = &self.buffer[start..];
+            if rest.len() < HEADER_LENGTH {
+                break;
+            }
+            let length = u16_le(rest, 4) as usize;
+            if length > MAX_PAYLOAD_LENGTH {
+                self.report.skipped_bytes += 1;
+                start += 1;
+                continue;
+            }
+            let frame_length = HEADER_LENGTH + length + CHECKSUM_LENGTH;
+            if rest.len() < frame_length {
+                break;
+            }
+
+            let (ck_a, ck_b) = checksum(&rest[2..HEADER_LENGTH + length]);
+            if (ck_a, ck_b) != (rest[frame_length - 2], rest[frame_length - 1]) {
+                // Possibly a false sync inside other data: only skip the sync byte.
+                self.report.bad_checksum += 1;
+                self.report.skipped_bytes += 1;
+                start += 1;
+                continue;
+            }
+
+            let class = rest[2];
+            let id = rest[3];
+            let payload = rest[HEADER_LENGTH..HEADER_LENGTH + length].to_vec();
+            self.report.frames += 1;
+            self.handle_message(class, id, &payload, records);
+            start += frame_length;
+        }
+        self.buffer.drain(..start);
+    }
+
+    fn handle_message(&mut self, class: u8, id: u8, payload: &[u8], records: &mut Vec<Record>) {
+        match (class, id) {
+            (CLASS_NAV, NAV_TIMEGPS) => self.handle_nav_timegps(payload),
+            (CLASS_NAV, NAV_SAT) => self.handle_nav_sat(payload),
+            (CLASS_NAV, NAV_SIG) => self.handle_nav_sig(payload, records),
+            (CLASS_RXM, RXM_RAWX) => self.handle_rxm_rawx(payload, records),
+            _ => self.report.ignored += 1,
+        }
+    }
+
+    /// Epoch of a navigation message from its GPS time of week, once the week is known.
+    fn itow_epoch(&mut self, itow: u32) -> Option<Epoch> {
+        match self.week {
+            Some(week) => Some(Epoch::from_gps_week_millis(week, itow as i64)),
+            None => {
+                self.report.undated += 1;
+                None
+            }
+        }
+    }
+
+    fn handle_nav_timegps(&mut self, p: &[u8]) {
+        if p.len() < 16 {
+            self.report.truncated += 1;
+            return;
+        }
+        let valid = p[11];
+        // Only trust the week number when the receiver flags it as valid.
+        if valid & 0x02 != 0 {
+            self.week = Some(u16_le(p, 8) as u32);
+        }
+    }
+
+    fn handle_nav_sat(&mut self, p: &[u8]) {
+        if p.len() < 8 || p.len() < 8 + 12 * p[5] as usize {
+            self.report.truncated += 1;
+            return;
+        }
+        self.report.nav_sat += 1;
+        let time = match self.itow_epoch(u32_le(p, 0)) {
+            Some(t) => t,
+            None => return,
+        };
+
+        self.geometry.clear();
+        for i in 0..p[5] as usize {
+            let block = &p[8 + 12 * i..8 + 12 * (i + 1)];
+            let elevation = block[3] as i8;
+            let azimuth = u16_le(block, 4) as i16;
+            let flags = u32_le(block, 8);
+            let orbit_source = (flags >> 8) & 0x07;
+            // Without orbit information the elevation and azimuth are meaningless.
+            if orbit_source == 0 || !(-90..=90).contains(&elevation) || !(0..=360).contains(&azimuth) {
+                continue;
+            }
+            self.geometry.insert(
+                (gnss_to_network(block[0]), block[1] as u32),
+                SatGeometry {
+                    elevation: elevation as f64,
+                    azimuth: azimuth as f64,
+                    in_use: flags & 0x08 != 0,
+                    healthy: (flags >> 4) & 0x03 != 2,
+                },
+            );
+        }
+        self.geometry_time = Some(time);
+    }
+
+    fn handle_nav_sig(&mut self, p: &[u8], records: &mut Vec<Record>) {
+        if p.len() < 8 || p.len() < 8 + 16 * p[5] as usize {
+            self.report.truncated += 1;
+            return;
+        }
+        self.report.nav_sig += 1;
+        if self.config.ubx_cno_source != CnoSource::NavSig {
+            return;
+        }
+        let time = match self.itow_epoch(u32_le(p, 0)) {
+            Some(t) => t,
+            None => return,
+        };
+
+        let start = records.len();
+        for i in 0..p[5] as usize {
+            let block = &p[8 + 16 * i..8 + 16 * (i + 1)];
+            let sig_flags = u16_le(block, 10);
+            let pr_used = sig_flags & 0x08 != 0;
+            self.push_record(time, block[0], block[1], block[2], block[6] as f64, Some(pr_used), records);
+        }
+        self.report.duplicate_signals += record::keep_first_signal(records, start);
+    }
+
+    fn handle_rxm_rawx(&mut self, p: &[u8], records: &mut Vec<Record>) {
+        if p.len() < 16 || p.len() < 16 + 32 * p[11] as usize {
+            self.report.truncated += 1;
+            return;
+        }
+        self.report.rawx += 1;
+
+        let week = u16_le(p, 8) as u32;
+        self.week = Some(week);
+        if self.config.ubx_cno_source != CnoSource::RawX {
+            return;
+        }
+
+        let tow_millis = (f64_le(p, 0) * 1000.0).round() as i64;
+        let time = Epoch::from_gps_week_millis(week, tow_millis);
+
+        let start = records.len();
+        for i in 0..p[11] as usize {
+            let block = &p[16 + 32 * i..16 + 32 * (i + 1)];
+            self.push_record(time, block[20], block[21], block[22], block[26] as f64, None, records);
+        }
+        self.report.duplicate_signals += record::keep_first_signal(records, start);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn push_record(&self, time: Epoch, gnss_id: u8, sv_id: u8, sig_id: u8, cno: f64, pr_used: Option<bool>, records: &mut Vec<Record>) {
+        // A C/N0 of zero means the signal is not tracked.
+        if cno <= 0.0 {
+            return;
+        }
+
+        let network = gnss_to_network(gnss_id);
+        let satellite = sv_id as u32;
+        if !self.config.networks.contains(&network) {
+            return;
+        }
+
+        let fresh = self.geometry_time.is_some_and(|t| (time - t).abs() * 1000.0 <= MAX_GEOMETRY_AGE as f64);
+        let geometry = match self.geometry.get(&(network, satellite)) {
+            Some(g) if fresh => *g,
+            _ => return,
+        };
+        if !self.config.accepts_geometry(geometry.elevation, geometry.azimuth) {
+            return;
+        }
+
+        let band = signal_to_band(network, sig_id);
+        records.push(Record {
+            id: Record::make_id(network, band, satellite),
+            satellite,
+            elevation: geometry.elevation,
+            azimuth: geometry.azimuth,
+            snr: cno,
+            time,
+            network,
+            band,
+            quality: Quality {
+                in_use: Some(pr_used.unwrap_or(geometry.in_use)),
+                faulty: !geometry.healthy,
+                ..Quality::default()
+            },
+        });
+    }
+}
+
+impl RecordParser for UbxParser {
+    fn push_bytes(&mut self, chunk: &[u8], records: &mut Vec<Record>) {
+        self.buffer.extend_from_slice(chunk);
+        self.drain_frames(records);
+    }
+
+    fn finish(&mut self, _records: &mut Vec<Record>) {
+        if !self.buffer.is_empty() {
+            self.report.truncated += 1;
+            self.report.skipped_bytes += self.buffer.len();
+            self.buffer.clear();
+        }
+    }
+
+    fn summary(&self) -> String {
+        format!("UBX: {}", self.report)
+    }
+}
+
+/// Everything extracted from a complete UBX stream.
+pub struct UbxLog {
+    pub records: Vec<Record>,
+    pub report: UbxReport,
+}
+
+/// Parses a complete UBX stream, e.g. a receiver log file.
+pub fn read_records<R: Read>(reader: R, config: &Config) -> io::Result<UbxLog> {
+    let mut source = StreamSource::new(reader, UbxParser::new(config));
+    let records = source.read_all()?;
+    Ok(UbxLog {
+        records,
+        report: source.parser().report().clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOW: f64 = 100_000.0;
+    const WEEK: u16 = 2000;
+
+    fn frame(class: u8, id: u8, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![SYNC_1, SYNC_2, class, id];
+        frame.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+        frame.extend_from_slice(payload);
+        let (ck_a, ck_b) = checksum(&frame[2..]);
+        frame.extend_from_slice(&[ck_a, ck_b]);
+        frame
+    }
+
+    /// NAV-SAT with GPS 05 at 5° elevation, 120° azimuth, used and healthy.
+    fn nav_sat() -> Vec<u8> {
+        let mut p = vec![0u8; 8 + 12];
+        p[0..4].copy_from_slice(&((TOW * 1000.0) as u32).to_le_bytes());
+        p[5] = 1;
+        p[8 + 1] = 5;
+        p[8 + 3] = 5;
+        p[8 + 4..8 + 6].copy_from_slice(&120u16.to_le_bytes());
+        p[8 + 8..8 + 12].copy_from_slice(&0x118u32.to_le_bytes());
+        frame(CLASS_NAV, NAV_SAT, &p)
+    }
+
+    /// RXM-RAWX with one GPS 05 measurement per given (sigId, C/N0).
+    fn rawx(signals: &[(u8, u8)]) -> Vec<u8> {
+        let mut p = vec![0u8; 16 + 32 * signals.len()];
+        p[0..8].copy_from_slice(&TOW.to_le_bytes());
+        p[8..10].copy_from_slice(&WEEK.to_le_bytes());
+        p[11] = signals.len() as u8;
+        for (i, &(sig_id, cno)) in signals.iter().enumerate() {
+            let block = &mut p[16 + 32 * i..16 + 32 * (i + 1)];
+            block[21] = 5;
+            block[22] = sig_id;
+            block[26] = cno;
+        }
+        frame(CLASS_RXM, RXM_RAWX, &p)
+    }
+
+    fn parse(bytes: &[u8]) -> (Vec<Record>, UbxReport) {
+        let mut parser = UbxParser::new(&Config::default());
+        let mut records = Vec::new();
+        parser.push_bytes(bytes, &mut records);
+        parser.finish(&mut records);
+        (records, parser.report().clone())
+    }
+
+    #[test]
+    fn fletcher_checksum() {
+        // The well-known NAV-PVT poll: B5 62 01 07 00 00 08 19.
+        assert_eq!(checksum(&[0x01, 0x07, 0x00, 0x00]), (0x08, 0x19));
+        assert_eq!(frame(CLASS_NAV, 0x07, &[]), [0xB5, 0x62, 0x01, 0x07, 0x00, 0x00, 0x08, 0x19]);
+    }
+
+    #[test]
+    fn valid_frames_give_a_record() {
+        let bytes = [rawx(&[]), nav_sat(), rawx(&[(0, 42)])].concat();
+        let (records, report) = parse(&bytes);
+        assert_eq!((report.frames, report.bad_checksum, report.skipped_bytes), (3, 0, 0));
+        assert_eq!(records.len(), 1);
+        let rec = &records[0];
+        assert_eq!((rec.network, rec.satellite, rec.band), (Network::GPS, 5, Band::L1));
+        assert_eq!((rec.elevation, rec.azimuth, rec.snr), (5.0, 120.0, 42.0));
+        assert_eq!(rec.time, Epoch::from_gps_week_millis(WEEK as u32, 100_000_000));
+        assert_eq!(rec.quality.in_use, Some(true));
+    }
+
+    #[test]
+    fn corrupted_frame_is_dropped_and_decoder_resyncs() {
+        let mut corrupted = rawx(&[(0, 42)]);
+        corrupted[HEADER_LENGTH + 26] ^= 0x01;
+        let garbage = b"$GPTXT,noise*00\r\n";
+        let bytes = [rawx(&[]), nav_sat(), corrupted, garbage.to_vec(), rawx(&[(0, 43)])].concat();
+        let (records, report) = parse(&bytes);
+        assert_eq!((report.frames, report.bad_checksum), (3, 1));
+        assert!(report.skipped_bytes >= garbage.len());
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].snr, 43.0);
+    }
+
+    #[test]
+    fn one_signal_per_band_and_epoch() {
+        // L2 CL and L2 CM share a band; only the first is kept.
+        let bytes = [rawx(&[]), nav_sat(), rawx(&[(0, 42), (3, 38), (4, 36)])].concat();
+        let (records, report) = parse(&bytes);
+        assert_eq!(records.iter().map(|r| (r.band, r.snr)).collect::<Vec<_>>(), vec![(Band::L1, 42.0), (Band::L2, 38.0)]);
+        assert_eq!(report.duplicate_signals, 1);
+    }
+}
@@ -1,26 +1,86 @@
+use realtime_gnssrefl::config::Config;
+use realtime_gnssrefl::gnssir::{self, ArcLog, ArcTracker};
+use realtime_gnssrefl::refraction::Weather;
+use realtime_gnssrefl::source::{self, InputFormat, ObservationSource, StreamSource};
+use realtime_gnssrefl::time::Epoch;
 use rppal::uart::{Parity, Uart};
-use std::time::Duration;
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The Pi's UART as a byte stream, so it can feed any of the record parsers.
+struct UartReader(Uart);
+
+impl io::Read for UartReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf).map_err(io::Error::other)
+    }
+}
+
+/// Prints the arcs the tracker completed, with the reflector height of each.
+fn report_arcs(log: ArcLog, weather: &Weather, config: &Config) {
+    for mut arc in log.arcs {
+        println!("Arc for ID {}: {}, {} records from {} to {}", arc.sat_id, arc.direction, arc.samples.len(), arc.time_start, arc.time_end);
+        if let Some((height, amplitude)) = gnssir::process_arc(&mut arc, weather, config) {
+            println!("Arc for ID {}: reflector height {:.2} m (amplitude {:.2})", arc.sat_id, height, amplitude);
+        }
+    }
+    for rejected in log.rejected {
+        println!("Rejected arc for ID {}: {}", rejected.arc.sat_id, rejected.reason);
+    }
+}
 
 fn main() {
     let mut uart = Uart::with_path("/dev/ttyAMA0", 115_200, Parity::None, 8, 1).expect("Failed to open UART");
     uart.set_read_mode(1, Duration::from_millis(0)).expect("Failed to set read mode");
 
+    // The receiver protocol is chosen on the command line: `compute ubx` or `compute nmea` (default).
+    let config = Config {
+        input_format: std::env::args().nth(1).and_then(|name| InputFormat::from_name(&name)),
+        ..Config::default()
+    };
+    let format = config.input_format.unwrap_or(InputFormat::Nmea);
+    // RTCM gives no elevation or azimuth, and there are no orbits here to compute them from.
+    if format == InputFormat::Rtcm {
+        eprintln!("RTCM input needs orbits for satellite geometry; run the receiver in UBX or NMEA mode");
+        std::process::exit(2);
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("System clock before 1970");
+    let parser = source::parser_for(format, &config, None, Epoch::from_unix_utc_millis(now.as_millis() as i64))
+        .expect("Failed to set up the parser");
+    let mut source = StreamSource::new(UartReader(uart), parser);
+    let mut tracker = ArcTracker::new(&config);
+    // Arcs hold their own samples, so records need not be kept once tracked.
+    let mut record_count: usize = 0;
+
     loop {
-        let mut buffer = [0u8; 1024];
-        
-        match uart.read(&mut buffer) {
-            Ok(bytes_read) if bytes_read > 0 => {
-                let data = &buffer[..bytes_read];
-                if let Ok(text) = std::str::from_utf8(data) {
-                    print!("{}", text);
-                } else {
-                    println!("{:?}", data);
+        match source.next_batch() {
+            Ok(Some(records)) => {
+                let mut log = ArcLog::default();
+                for record in &records {
+                    let completed = tracker.push(record);
+                    log.arcs.extend(completed.arcs);
+                    log.rejected.extend(completed.rejected);
                 }
+                record_count += records.len();
+                if let Some(last) = records.last() {
+                    println!("{} records, {} arcs open at {}", record_count, tracker.open_arcs(), last.time);
+                }
+                let weather = Weather::at_station(&source.station());
+                report_arcs(log, &weather, &config);
+            }
+            Ok(None) => break,
+            Err(e) if matches!(e.kind(), io::ErrorKind::Interrupted | io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock) => {
+                eprintln!("UART read error: {}, retrying", e);
             }
-            Ok(_) => {}
             Err(e) => {
-                eprintln!("UART read error: {:?}", e);
+                eprintln!("UART read failed: {}", e);
+                break;
             }
         }
     }
+
+    // Whatever arcs are still open end with the stream.
+    let weather = Weather::at_station(&source.station());
+    report_arcs(tracker.finish(), &weather, &config);
 }